@@ -0,0 +1,352 @@
+//! Async execution of `Intcode` without tying it to a runtime.
+//!
+//! `split` turns a VM into a `Machine` future plus an `Input` sink and an `Output` stream.
+//! The machine runs until the VM needs input nobody has sent yet, then yields until `Input::send`
+//!  wakes it. It also yields every `SLICE` instructions so one executor can interleave many VMs.
+//! `Output::poll_next` has the signature of a futures `Stream`, so wrapping it is one line for
+//!  hosts using that crate. `Executor` and `block_on` are a minimal single-thread runtime.
+use super::{
+    error::IntcodeError,
+    memory::{Memory, Paged},
+    vm::Intcode,
+    DWord,
+};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    thread::{self, Thread},
+};
+
+/// Instructions a machine executes per poll before yielding.
+pub const SLICE: usize = 1024;
+
+#[derive(Default)]
+struct Shared {
+    inputs: VecDeque<DWord>,
+    /// Live `Input` handles, the last one closes input when dropped.
+    senders: usize,
+    closed: bool,
+    outputs: VecDeque<DWord>,
+    finished: bool,
+    machine: Option<Waker>,
+    reader: Option<Waker>,
+}
+
+type Link = Arc<Mutex<Shared>>;
+
+/// Sends input to a machine, clones feed the same one. Dropping the last one closes it.
+pub struct Input(Link);
+
+impl Clone for Input {
+    fn clone(&self) -> Self {
+        self.0.lock().unwrap().senders += 1;
+        Input(self.0.clone())
+    }
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        let last = {
+            let mut shared = self.0.lock().unwrap();
+            shared.senders -= 1;
+            shared.senders == 0
+        };
+        if last {
+            self.close();
+        }
+    }
+}
+
+impl Input {
+    pub fn send(&self, value: DWord) {
+        let mut shared = self.0.lock().unwrap();
+        shared.inputs.push_back(value);
+        if let Some(waker) = shared.machine.take() {
+            waker.wake();
+        }
+    }
+
+    /// No more input is coming, a machine waiting for some fails with `InputClosed`.
+    pub fn close(&self) {
+        let mut shared = self.0.lock().unwrap();
+        shared.closed = true;
+        if let Some(waker) = shared.machine.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Everything a machine outputs, in order, ending once it has finished.
+pub struct Output(Link);
+
+impl Output {
+    pub fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<DWord>> {
+        let mut shared = self.0.lock().unwrap();
+        match shared.outputs.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if shared.finished => Poll::Ready(None),
+            None => {
+                shared.reader = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Waits for the next output, None once the machine has finished.
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv(self)
+    }
+}
+
+pub struct Recv<'a>(&'a mut Output);
+
+impl Future for Recv<'_> {
+    type Output = Option<DWord>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<DWord>> {
+        self.0.poll_next(cx)
+    }
+}
+
+/// Runs the VM, resolves to it once it halts or to the error that stopped it.
+pub struct Machine<M: Memory = Paged> {
+    vm: Option<Intcode<M>>,
+    link: Link,
+}
+
+/// Splits a VM into the future that runs it and its two ends.
+pub fn split<M: Memory>(vm: Intcode<M>) -> (Machine<M>, Input, Output) {
+    let link = Link::default();
+    link.lock().unwrap().senders = 1;
+    (Machine{vm: Some(vm), link: link.clone()}, Input(link.clone()), Output(link))
+}
+
+impl<M: Memory> Machine<M> {
+    fn finish(&mut self, result: Result<(), IntcodeError>) -> Poll<Result<Intcode<M>, IntcodeError>> {
+        let mut shared = self.link.lock().unwrap();
+        shared.finished = true;
+        if let Some(waker) = shared.reader.take() {
+            waker.wake();
+        }
+        let vm = self.vm.take().expect("machine polled after it finished");
+        Poll::Ready(result.map(|_| vm))
+    }
+}
+
+/// Nothing in a machine is ever pinned in place.
+impl<M: Memory> Unpin for Machine<M> {}
+
+impl<M: Memory> Future for Machine<M> {
+    type Output = Result<Intcode<M>, IntcodeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        for _ in 0..SLICE {
+            let vm = this.vm.as_mut().expect("machine polled after it finished");
+            match vm.step() {
+                Ok(Some(value)) => {
+                    let mut shared = this.link.lock().unwrap();
+                    shared.outputs.push_back(value);
+                    if let Some(waker) = shared.reader.take() {
+                        waker.wake();
+                    }
+                }
+                Ok(None) => (),
+                Err(IntcodeError::NeedsInput) => {
+                    let mut shared = this.link.lock().unwrap();
+                    if let Some(value) = shared.inputs.pop_front() {
+                        vm.inputs.push_back(value);
+                    } else if shared.closed {
+                        drop(shared);
+                        return this.finish(Err(IntcodeError::InputClosed));
+                    } else {
+                        shared.machine = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                }
+                Err(IntcodeError::Halted) => return this.finish(Ok(())),
+                Err(e) => return this.finish(Err(e)),
+            }
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Wakes a task by flagging it and unparking the thread running the executor.
+struct Signal {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+fn raw_waker(signal: Arc<Signal>) -> RawWaker {
+    RawWaker::new(Arc::into_raw(signal) as *const (), &VTABLE)
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    let signal = Arc::from_raw(data as *const Signal);
+    let clone = signal.clone();
+    std::mem::forget(signal);
+    raw_waker(clone)
+}
+
+unsafe fn wake(data: *const ()) {
+    wake_by_ref(data);
+    drop_waker(data);
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    let signal = &*(data as *const Signal);
+    signal.woken.store(true, Ordering::SeqCst);
+    signal.thread.unpark();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    drop(Arc::from_raw(data as *const Signal));
+}
+
+fn signal() -> Arc<Signal> {
+    Arc::new(Signal{woken: AtomicBool::new(true), thread: thread::current()})
+}
+
+fn waker(signal: &Arc<Signal>) -> Waker {
+    // Safe because the vtable keeps the reference count of the `Arc` it was made from.
+    unsafe { Waker::from_raw(raw_waker(signal.clone())) }
+}
+
+/// Runs a future to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let signal = signal();
+    let waker = waker(&signal);
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if signal.woken.swap(false, Ordering::SeqCst) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        } else {
+            thread::park();
+        }
+    }
+}
+
+/// Runs many tasks on the current thread, polling only those that were woken.
+#[derive(Default)]
+pub struct Executor {
+    tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F: Future<Output = ()> + 'static>(&mut self, task: F) -> &mut Self {
+        self.tasks.push(Box::pin(task));
+        self
+    }
+
+    /// Runs until every spawned task has completed.
+    pub fn run(&mut self) {
+        let mut tasks: Vec<_> = self.tasks.drain(..).map(|task| (signal(), task)).collect();
+        while !tasks.is_empty() {
+            let mut polled = false;
+            let mut i = 0;
+            while i < tasks.len() {
+                let (signal, task) = &mut tasks[i];
+                if signal.woken.swap(false, Ordering::SeqCst) {
+                    polled = true;
+                    let waker = waker(signal);
+                    if task.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                        drop(tasks.swap_remove(i));
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+            if !polled {
+                thread::park();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn doubler() -> Result<(), IntcodeError> {
+        let (machine, input, mut output) = split("3,9,1002,9,2,9,4,9,99,0".parse::<Intcode>()?);
+        input.send(21);
+        let vm = block_on(machine)?;
+        assert_eq!(vm[9], 42);
+        assert_eq!(block_on(output.recv()), Some(42));
+        assert_eq!(block_on(output.recv()), None);
+
+        let (machine, input, _) = split("3,0,99".parse::<Intcode>()?);
+        input.close();
+        match block_on(machine) {
+            Err(IntcodeError::InputClosed) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Dropping every input closes it as well, so the machine doesn't wait forever.
+        let (machine, input, _) = split("3,0,3,0,99".parse::<Intcode>()?);
+        let clone = input.clone();
+        input.send(1);
+        drop(input);
+        let mut executor = Executor::new();
+        let result = Rc::new(Cell::new(None));
+        let done = result.clone();
+        executor.spawn(async move { done.set(Some(machine.await.map(|_| ()))); });
+        executor.spawn(async move { drop(clone); });
+        executor.run();
+        match result.take() {
+            Some(Err(IntcodeError::InputClosed)) => Ok(()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn feedback_loop() {
+        // Day 7 example, the amplifiers pass signals around until they halt.
+        let code = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+        let (mut machines, mut inputs, mut outputs) = (Vec::new(), Vec::new(), Vec::new());
+        for &phase in [9, 8, 7, 6, 5].iter() {
+            let (machine, input, output) = split(code.parse::<Intcode>().unwrap());
+            input.send(phase);
+            machines.push(machine);
+            inputs.push(input);
+            outputs.push(output);
+        }
+        inputs[0].send(0);
+        inputs.rotate_left(1);
+
+        let last = Rc::new(Cell::new(None));
+        let mut executor = Executor::new();
+        for machine in machines {
+            executor.spawn(async move { machine.await.unwrap(); });
+        }
+        for (i, (mut output, next)) in outputs.into_iter().zip(inputs).enumerate() {
+            let last = last.clone();
+            executor.spawn(async move {
+                while let Some(value) = output.recv().await {
+                    next.send(value);
+                    if i == 4 {
+                        last.set(Some(value));
+                    }
+                }
+            });
+        }
+        executor.run();
+        assert_eq!(last.get(), Some(139_629_729));
+    }
+}
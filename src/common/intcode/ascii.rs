@@ -0,0 +1,158 @@
+//! Text I/O for Intcode programs that talk in ASCII.
+//!
+//! Input lines are encoded one character per value with a trailing newline,
+//!  outputs are collected into lines. Values outside of ASCII are reported on their own,
+//!  as programs usually use them for the final result.
+use super::{
+    error::IntcodeError,
+    memory::{Memory, Paged},
+    vm::Intcode,
+    DWord,
+};
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A complete line of output, without the newline.
+    Line(String),
+    /// Text output without a newline, right before the program asked for input or halted.
+    Prompt(String),
+    /// An output value that isn't ASCII.
+    Value(DWord),
+    NeedsInput,
+    Halted,
+}
+
+#[derive(Clone, Debug)]
+pub struct Ascii<M: Memory = Paged> {
+    vm: Intcode<M>,
+    partial: String,
+}
+
+impl<M: Memory> From<Intcode<M>> for Ascii<M> {
+    fn from(vm: Intcode<M>) -> Self {
+        Self{vm, partial: String::new()}
+    }
+}
+
+impl<M: Memory> Ascii<M> {
+    pub fn vm(&self) -> &Intcode<M> {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Intcode<M> {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> Intcode<M> {
+        self.vm
+    }
+
+    /// Queues `line` followed by a newline as input.
+    pub fn send_line(&mut self, line: &str) -> &mut Self {
+        self.vm.inputs.extend(line.bytes().map(DWord::from));
+        self.vm.inputs.push_back(b'\n'.into());
+        self
+    }
+
+    /// Runs the VM until the next thing worth reporting happens.
+    pub fn next_event(&mut self) -> Result<Event, IntcodeError> {
+        loop {
+            match self.vm.step() {
+                Ok(Some(value)) if value == b'\n'.into() => {
+                    return Ok(Event::Line(std::mem::take(&mut self.partial)));
+                }
+                Ok(Some(value)) if (0..128).contains(&value) => self.partial.push(value as u8 as char),
+                Ok(Some(value)) => return Ok(Event::Value(value)),
+                Ok(None) => (),
+                Err(IntcodeError::NeedsInput) | Err(IntcodeError::Halted) if !self.partial.is_empty() => {
+                    return Ok(Event::Prompt(std::mem::take(&mut self.partial)));
+                }
+                Err(IntcodeError::NeedsInput) => return Ok(Event::NeedsInput),
+                Err(IntcodeError::Halted) => return Ok(Event::Halted),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Collects events until the program asks for input or halts, that last event included.
+    pub fn run(&mut self) -> Result<Vec<Event>, IntcodeError> {
+        let mut events = Vec::new();
+        loop {
+            let event = self.next_event()?;
+            let done = event == Event::NeedsInput || event == Event::Halted;
+            events.push(event);
+            if done {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Bridges the program to a terminal until it halts or `input` runs out.
+    ///
+    /// Pass `io::stdin().lock()` and `io::stdout()` to play a text-based program directly.
+    pub fn interactive<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+        loop {
+            match self.next_event() {
+                Ok(Event::Line(line)) => writeln!(output, "{}", line)?,
+                Ok(Event::Prompt(text)) => write!(output, "{}", text)?,
+                Ok(Event::Value(value)) => writeln!(output, "non-ASCII result: {}", value)?,
+                Ok(Event::NeedsInput) => {
+                    output.flush()?;
+                    let mut line = String::new();
+                    if input.read_line(&mut line)? == 0 {
+                        return Ok(());
+                    }
+                    self.send_line(line.trim_end_matches(['\r', '\n']));
+                }
+                Ok(Event::Halted) => return output.flush(),
+                Err(e) => {
+                    writeln!(output, "error: {}", e)?;
+                    return output.flush();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREETER: &str = "
+        out #72
+        out #105
+        out #10
+        out #1000
+        out #62
+        in [100]
+        out [100]
+        hlt
+    ";
+
+    fn greeter() -> Ascii {
+        Ascii::from(Intcode::new(super::super::asm::assemble(GREETER).unwrap(), vec![]))
+    }
+
+    #[test]
+    fn events() -> Result<(), IntcodeError> {
+        let mut ascii = greeter();
+        assert_eq!(ascii.run()?, vec![
+            Event::Line("Hi".to_owned()),
+            Event::Value(1000),
+            Event::Prompt(">".to_owned()),
+            Event::NeedsInput,
+        ]);
+        assert_eq!(ascii.send_line("A").run()?, vec![Event::Prompt("A".to_owned()), Event::Halted]);
+        // The newline is still queued.
+        assert_eq!(ascii.vm().inputs, vec![10]);
+        Ok(())
+    }
+
+    #[test]
+    fn interactive() {
+        let mut out = Vec::new();
+        greeter().interactive("A\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Hi\nnon-ASCII result: 1000\n>A");
+    }
+}
@@ -0,0 +1,259 @@
+//! Assembles a small textual Intcode syntax into program memory.
+//!
+//! The syntax is the one `disasm::listing` produces, plus labels and comments:
+//!
+//! ```text
+//! ; compare input against 8
+//! start:  in [value]
+//!         eq [value], #8, [value]
+//!         out [value]
+//!         hlt
+//! value:  .data -1
+//! ```
+//!
+//! Operands are `#imm` (immediate), `[addr]` (position) and `[rb+off]` / `[rb-off]` / `[rb]` (relative).
+//! Immediate and position operands as well as `.data` values may refer to `label`, `label+N` or `label-N`.
+//! A line may start with a numeric address like `0004:`, which is checked against the assembled address.
+use super::{
+    error::AsmError,
+    instruction::{Instruction, Mode, Op, Param},
+    DWord,
+};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(DWord),
+    Label(String, DWord),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Instruction(Op, Vec<(Mode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Item::Instruction(op, _) => 1 + op.arity(),
+            Item::Data(values) => values.len(),
+        }
+    }
+}
+
+/// Assembles `source` into memory ready for `Intcode::new`.
+pub fn assemble(source: &str) -> Result<Vec<DWord>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut addr = 0;
+
+    // First pass: parse every line and lay out addresses, so labels can be referenced before definition.
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut rest = raw.split(';').next().unwrap_or("").trim();
+        while let Some((name, tail)) = split_label(rest) {
+            if let Ok(expected) = name.parse::<usize>() {
+                if expected != addr {
+                    return Err(AsmError::AddressMismatch{line, expected, actual: addr});
+                }
+            } else if labels.insert(name.to_owned(), addr).is_some() {
+                return Err(AsmError::DuplicateLabel{line, label: name.to_owned()});
+            }
+            rest = tail;
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let item = parse_item(line, rest)?;
+        addr += item.size();
+        items.push((line, item));
+    }
+
+    // Second pass: resolve labels and encode.
+    let mut memory = Vec::with_capacity(addr);
+    for (line, item) in items {
+        match item {
+            Item::Instruction(op, operands) => {
+                let params = operands.into_iter()
+                    .map(|(mode, expr)| Ok(Param{mode, value: resolve(line, &labels, expr)?}))
+                    .collect::<Result<Vec<Param>, AsmError>>()?;
+                memory.extend(Instruction{op, params}.encode());
+            }
+            Item::Data(values) => {
+                for expr in values {
+                    memory.push(resolve(line, &labels, expr)?);
+                }
+            }
+        }
+    }
+    Ok(memory)
+}
+
+/// Splits off a leading `name:` if the line has one.
+fn split_label(s: &str) -> Option<(&str, &str)> {
+    let colon = s.find(':')?;
+    let name = &s[..colon];
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Some((name, s[colon + 1..].trim_start()))
+    } else {
+        None
+    }
+}
+
+fn parse_item(line: usize, s: &str) -> Result<Item, AsmError> {
+    let (mnemonic, rest) = match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim()),
+        None => (s, ""),
+    };
+    let operands: Vec<&str> = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').map(str::trim).collect()
+    };
+
+    if mnemonic == ".data" {
+        let values = operands.iter()
+            .map(|o| parse_expr(o).ok_or_else(|| bad_operand(line, o)))
+            .collect::<Result<Vec<Expr>, AsmError>>()?;
+        return Ok(Item::Data(values));
+    }
+
+    let op = Op::from_mnemonic(mnemonic)
+        .ok_or_else(|| AsmError::UnknownMnemonic{line, mnemonic: mnemonic.to_owned()})?;
+    if operands.len() != op.arity() {
+        return Err(AsmError::WrongArity{
+            line, mnemonic: mnemonic.to_owned(), expected: op.arity(), got: operands.len(),
+        });
+    }
+    let operands = operands.iter()
+        .enumerate()
+        .map(|(index, o)| {
+            let operand = parse_operand(o).ok_or_else(|| bad_operand(line, o))?;
+            if operand.0 == Mode::Immediate && op.write_param() == Some(index) {
+                return Err(AsmError::ImmediateWrite{line, mnemonic: mnemonic.to_owned(), index});
+            }
+            Ok(operand)
+        })
+        .collect::<Result<Vec<(Mode, Expr)>, AsmError>>()?;
+    Ok(Item::Instruction(op, operands))
+}
+
+fn parse_operand(s: &str) -> Option<(Mode, Expr)> {
+    if let Some(imm) = s.strip_prefix('#') {
+        return Some((Mode::Immediate, parse_expr(imm)?));
+    }
+    let inner = s.strip_prefix('[')?.strip_suffix(']')?.trim();
+    if inner == "rb" {
+        return Some((Mode::Relative, Expr::Number(0)));
+    }
+    if let Some(offset) = inner.strip_prefix("rb") {
+        let offset = offset.trim();
+        let value = if let Some(n) = offset.strip_prefix('+') {
+            n.trim().parse().ok()?
+        } else {
+            -offset.strip_prefix('-')?.trim().parse::<DWord>().ok()?
+        };
+        return Some((Mode::Relative, Expr::Number(value)));
+    }
+    Some((Mode::Position, parse_expr(inner)?))
+}
+
+fn parse_expr(s: &str) -> Option<Expr> {
+    let s = s.trim();
+    if let Ok(n) = s.parse() {
+        return Some(Expr::Number(n));
+    }
+    let (name, offset) = match s.find(['+', '-']) {
+        Some(i) => {
+            let n: DWord = s[i + 1..].trim().parse().ok()?;
+            (s[..i].trim(), if &s[i..=i] == "-" { -n } else { n })
+        }
+        None => (s, 0),
+    };
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Some(Expr::Label(name.to_owned(), offset))
+    } else {
+        None
+    }
+}
+
+fn resolve(line: usize, labels: &HashMap<String, usize>, expr: Expr) -> Result<DWord, AsmError> {
+    match expr {
+        Expr::Number(n) => Ok(n),
+        Expr::Label(label, offset) => match labels.get(&label) {
+            Some(&addr) => Ok(addr as DWord + offset),
+            None => Err(AsmError::UnknownLabel{line, label}),
+        },
+    }
+}
+
+fn bad_operand(line: usize, operand: &str) -> AsmError {
+    AsmError::BadOperand{line, operand: operand.to_owned()}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{disasm::listing, vm::Intcode};
+
+    #[test]
+    fn labels_and_comments() -> Result<(), AsmError> {
+        let code = assemble("
+            ; day 5 'equal to 8' example, hand written
+            start:  in [value]
+                    eq [value], #8, [value]
+                    out [value]
+                    hlt
+            value:  .data -1
+        ")?;
+        assert_eq!(code, vec![3,9,1008,9,8,9,4,9,99,-1]);
+        let out: Vec<_> = Intcode::new(code, vec![8]).collect::<Result<_, _>>().unwrap();
+        assert_eq!(out, vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn forward_references() -> Result<(), AsmError> {
+        let code = assemble("
+            jt #1, #end
+            out #-1
+            end: out [rb-2]
+            hlt
+        ")?;
+        assert_eq!(code, vec![1105,1,5,104,-1,204,-2,99]);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), AsmError> {
+        let quine = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        assert_eq!(assemble(&listing(&quine))?, quine);
+        let day5 = vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
+        assert_eq!(assemble(&listing(&day5))?, day5);
+        Ok(())
+    }
+
+    #[test]
+    fn errors_have_lines() {
+        match assemble("hlt\nfoo #1") {
+            Err(AsmError::UnknownMnemonic{line: 2, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match assemble("out [nowhere]") {
+            Err(AsmError::UnknownLabel{line: 1, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match assemble("in #4") {
+            Err(AsmError::ImmediateWrite{line: 1, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match assemble("hlt\n0002: hlt") {
+            Err(AsmError::AddressMismatch{line: 2, expected: 2, actual: 1}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Intcode with arbitrary-precision words, for programs that outgrow 64 bits on purpose.
+//!
+//! Only built with the `bigint` feature. Slower than `Intcode`, so use that unless you need this.
+//! Addresses still have to fit into `usize`. Errors show values clamped to the `DWord` range.
+use super::{
+    error::{Fault, IntcodeError},
+    instruction::{Mode, Op},
+    vm::State,
+    DWord,
+};
+use num_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::collections::{BTreeMap, VecDeque};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigIntcode {
+    pc: usize,
+    state: State,
+    base: BigInt,
+    executed: u64,
+    pub memory: BTreeMap<usize, BigInt>,
+    pub inputs: VecDeque<BigInt>,
+}
+
+impl BigIntcode {
+    pub fn new(code: Vec<BigInt>, inputs: Vec<BigInt>) -> Self {
+        BigIntcode {
+            pc: 0,
+            state: State::Ready,
+            base: BigInt::zero(),
+            executed: 0,
+            memory: code.into_iter().enumerate().collect(),
+            inputs: VecDeque::from(inputs),
+        }
+    }
+
+    /// Returns current state of the VM.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Returns current relative base.
+    pub fn base(&self) -> &BigInt {
+        &self.base
+    }
+
+    /// Returns number of instructions executed so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    fn get(&self, addr: usize) -> BigInt {
+        self.memory.get(&addr).cloned().unwrap_or_else(BigInt::zero)
+    }
+
+    fn set(&mut self, addr: usize, value: BigInt) {
+        self.memory.insert(addr, value);
+    }
+
+    /// Same as `Intcode::step`, arithmetic never overflows.
+    pub fn step(&mut self) -> Result<Option<BigInt>, IntcodeError> {
+        if self.state == State::Halted {
+            return Err(IntcodeError::Halted);
+        }
+        let (args, op) = self.decode()?;
+        let mut output = None;
+        let flag = |condition: bool| if condition { BigInt::one() } else { BigInt::zero() };
+        let new_pc = match op {
+            Op::Add => {
+                self.set(args[2], self.get(args[0]) + self.get(args[1]));
+                self.pc + 4
+            }
+            Op::Mul => {
+                self.set(args[2], self.get(args[0]) * self.get(args[1]));
+                self.pc + 4
+            }
+            Op::In => match self.inputs.pop_front() {
+                Some(value) => {
+                    self.set(args[0], value);
+                    self.state = State::Ready;
+                    self.pc + 2
+                }
+                None => {
+                    self.state = State::WaitingForInput;
+                    return Err(IntcodeError::NeedsInput);
+                }
+            }
+            Op::Out => {
+                output = Some(self.get(args[0]));
+                self.pc + 2
+            }
+            Op::JumpIfTrue if !self.get(args[0]).is_zero() => self.address(&self.get(args[1]))?,
+            Op::JumpIfFalse if self.get(args[0]).is_zero() => self.address(&self.get(args[1]))?,
+            Op::JumpIfTrue | Op::JumpIfFalse => self.pc + 3,
+            Op::LessThan => {
+                self.set(args[2], flag(self.get(args[0]) < self.get(args[1])));
+                self.pc + 4
+            }
+            Op::Equals => {
+                self.set(args[2], flag(self.get(args[0]) == self.get(args[1])));
+                self.pc + 4
+            }
+            Op::AdjustBase => {
+                self.base += self.get(args[0]);
+                self.pc + 2
+            }
+            Op::Halt => {
+                self.state = State::Halted;
+                self.executed += 1;
+                return Err(IntcodeError::Halted);
+            }
+        };
+        self.pc = new_pc;
+        self.executed += 1;
+        Ok(output)
+    }
+
+    /// Resolves the address of every parameter of the instruction at pc.
+    fn decode(&self) -> Result<(Vec<usize>, Op), IntcodeError> {
+        let word = self.get(self.pc);
+        let op = match word.to_i64().filter(|&w| w >= 0).and_then(|w| Op::from_code(w % 100)) {
+            Some(op) => op,
+            None => return Err(IntcodeError::UnknownOpcode{opcode: clamp(&word), fault: self.fault()}),
+        };
+        let mut modes = clamp(&word) / 100;
+        let mut args = Vec::with_capacity(op.arity());
+        for param in 0..op.arity() {
+            let at = self.pc + 1 + param;
+            let addr = match Mode::from_digit(modes % 10) {
+                Some(Mode::Position) => self.address(&self.get(at))?,
+                Some(Mode::Immediate) if op.write_param() == Some(param) => {
+                    return Err(IntcodeError::ImmediateWrite{param, fault: self.fault()});
+                }
+                Some(Mode::Immediate) => at,
+                Some(Mode::Relative) => self.address(&(&self.base + self.get(at)))?,
+                None => return Err(IntcodeError::InvalidMode{mode: modes % 10, param, fault: self.fault()}),
+            };
+            args.push(addr);
+            modes /= 10;
+        }
+        Ok((args, op))
+    }
+
+    fn address(&self, addr: &BigInt) -> Result<usize, IntcodeError> {
+        if addr.is_negative() {
+            return Err(IntcodeError::NegativeAddress{addr: clamp(addr), fault: self.fault()});
+        }
+        addr.to_usize().ok_or_else(|| IntcodeError::Overflow{fault: self.fault()})
+    }
+
+    fn fault(&self) -> Box<Fault> {
+        let start = self.pc.saturating_sub(4);
+        let memory = (start..self.pc + 8).map(|addr| clamp(&self.get(addr))).collect();
+        Fault::new(self.pc, clamp(&self.get(self.pc)), clamp(&self.base), self.executed, start, memory)
+    }
+}
+
+fn clamp(value: &BigInt) -> DWord {
+    value.to_i64().unwrap_or(if value.is_negative() { DWord::MIN } else { DWord::MAX })
+}
+
+impl From<&[DWord]> for BigIntcode {
+    fn from(code: &[DWord]) -> Self {
+        BigIntcode::new(code.iter().map(|&word| BigInt::from(word)).collect(), Vec::new())
+    }
+}
+
+impl Iterator for BigIntcode {
+    type Item = Result<BigInt, IntcodeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step() {
+                Ok(Some(o)) => return Some(Ok(o)),
+                Err(IntcodeError::Halted) => return None,
+                Err(e) => return Some(Err(e)),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn past_64_bits() -> Result<(), IntcodeError> {
+        // Computes 2^64, squares it and outputs that through the relative base.
+        let mut vm = BigIntcode::from(&[109, 20, 1102, 4611686018427387904, 4, 20, 2, 20, 20, 21, 204, 1, 99][..]);
+        let output = vm.by_ref().collect::<Result<Vec<BigInt>, IntcodeError>>()?;
+        assert_eq!(output, vec!["340282366920938463463374607431768211456".parse::<BigInt>().unwrap()]);
+        assert_eq!(vm.state(), State::Halted);
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! Limits on how much work `Intcode::step` may do.
+//!
+//! Limits are checked before every instruction, so an exhausted budget leaves the VM untouched
+//!  and it carries on once the budget is raised or cleared.
+//! Instructions and outputs are counted over the whole life of the VM, so limits are totals:
+//!  raising a limit from 10 to 20 instructions allows 10 more, not 20.
+//! Memory is counted in distinct cells written while the limit is set, whatever the backend
+//!  allocates for them, and the target of a write is checked before the instruction runs.
+use std::{
+    collections::BTreeSet,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The limit that stopped execution.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions(u64),
+    /// Distinct cells written.
+    Memory(usize),
+    Outputs(u64),
+    Deadline,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(n) => write!(f, "{} instructions", n),
+            Limit::Memory(n) => write!(f, "{} memory cells", n),
+            Limit::Outputs(n) => write!(f, "{} outputs", n),
+            Limit::Deadline => write!(f, "deadline"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    instructions: Option<u64>,
+    memory: Option<usize>,
+    outputs: Option<u64>,
+    deadline: Option<Instant>,
+    spent_instructions: u64,
+    spent_outputs: u64,
+    /// Cells written while a memory limit is set.
+    written: BTreeSet<usize>,
+}
+
+impl Budget {
+    /// Unlimited budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow at most `n` instructions in total, including those already spent.
+    pub fn instructions(&mut self, n: u64) -> &mut Self {
+        self.instructions = Some(n);
+        self
+    }
+
+    /// Stop before a write to a new cell once `cells` distinct cells have been written.
+    ///
+    /// Only writes made while the limit is set count, loading the code doesn't.
+    pub fn memory(&mut self, cells: usize) -> &mut Self {
+        self.memory = Some(cells);
+        self
+    }
+
+    /// Allow at most `n` outputs in total, including those already spent.
+    pub fn outputs(&mut self, n: u64) -> &mut Self {
+        self.outputs = Some(n);
+        self
+    }
+
+    pub fn deadline(&mut self, deadline: Instant) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline `duration` from now.
+    pub fn timeout(&mut self, duration: Duration) -> &mut Self {
+        self.deadline(Instant::now() + duration)
+    }
+
+    pub fn spent_instructions(&self) -> u64 {
+        self.spent_instructions
+    }
+
+    pub fn spent_outputs(&self) -> u64 {
+        self.spent_outputs
+    }
+
+    /// Whether writes have to go through `check_write` and `spend`.
+    pub(super) fn tracks_memory(&self) -> bool {
+        self.memory.is_some()
+    }
+
+    pub(super) fn check(&self) -> Result<(), Limit> {
+        match *self {
+            Budget{instructions: Some(n), spent_instructions, ..} if spent_instructions >= n => Err(Limit::Instructions(n)),
+            Budget{outputs: Some(n), spent_outputs, ..} if spent_outputs >= n => Err(Limit::Outputs(n)),
+            Budget{deadline: Some(deadline), ..} if Instant::now() >= deadline => Err(Limit::Deadline),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the cell the next instruction writes to, if any.
+    pub(super) fn check_write(&self, addr: Option<usize>) -> Result<(), Limit> {
+        match (self.memory, addr) {
+            (Some(n), Some(addr)) if self.written.len() >= n && !self.written.contains(&addr) => Err(Limit::Memory(n)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn spend(&mut self, output: bool, write: Option<usize>) {
+        self.spent_instructions += 1;
+        if output {
+            self.spent_outputs += 1;
+        }
+        if let (Some(_), Some(addr)) = (self.memory, write) {
+            self.written.insert(addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::intcode::{error::IntcodeError, memory::{Dense, Memory}, vm::Intcode};
+
+    #[test]
+    fn infinite_loop_is_resumable() {
+        // Counts forever at [5] without any output.
+        let mut vm = "1001,5,1,5,1105,0,0".parse::<Intcode>().unwrap();
+        vm.budget_mut().instructions(10);
+        match vm.next() {
+            Some(Err(IntcodeError::BudgetExhausted{limit: Limit::Instructions(10), ..})) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!((vm.executed(), vm[5]), (10, 5));
+
+        vm.budget_mut().instructions(20);
+        assert!(vm.next().unwrap().is_err());
+        assert_eq!((vm.executed(), vm[5]), (20, 10));
+    }
+
+    #[test]
+    fn limits() {
+        let mut vm = "104,1,104,2,104,3,99".parse::<Intcode>().unwrap();
+        vm.budget_mut().outputs(2);
+        let outputs: Vec<_> = vm.by_ref().take(3).collect();
+        assert_eq!(outputs[..2].iter().map(|o| *o.as_ref().unwrap()).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(outputs[2].is_err());
+
+        // On the default paged backend a far write counts as one cell, and rewriting a cell is free.
+        let mut vm = "1101,1,1,100,1101,1,1,1000000,1101,2,2,100,1101,1,1,5000,99".parse::<Intcode>().unwrap();
+        vm.budget_mut().memory(2);
+        match vm.next() {
+            Some(Err(IntcodeError::BudgetExhausted{limit: Limit::Memory(2), fault})) => assert_eq!(fault.pc, 12),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!((vm.pc(), vm[100], vm[1_000_000], vm[5000]), (12, 4, 2, 0));
+        vm.budget_mut().memory(3);
+        assert_eq!(vm.next().map(|o| o.is_ok()), None);
+
+        // The write is refused before the dense backend grows to reach it.
+        let mut vm = Intcode::<Dense>::with_backend(vec![1101, 1, 1, 1 << 40, 99], vec![]);
+        vm.budget_mut().memory(0);
+        assert!(vm.next().unwrap().is_err());
+        assert_eq!(vm.memory_mut().allocated(), 5);
+
+        let mut vm = "99".parse::<Intcode>().unwrap();
+        vm.budget_mut().deadline(Instant::now());
+        assert!(vm.next().unwrap().is_err());
+    }
+}
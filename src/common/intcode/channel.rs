@@ -0,0 +1,202 @@
+//! Channel-style adapter on top of the stepping `Intcode`.
+//!
+//! Runs the VM to completion reading inputs from an `mpsc::Receiver` and writing outputs to an `mpsc::Sender`,
+//!  which makes it easy to run on its own thread or wire several VMs together.
+//! Halting sends a final `None` on the output channel.
+use super::{
+    error::IntcodeError,
+    load,
+    memory::{Memory, Paged},
+    vm::Intcode,
+    DWord,
+};
+use std::{str::FromStr, sync::mpsc};
+
+pub struct IntcodeVM<M: Memory = Paged> {
+    vm: Intcode<M>,
+    input: mpsc::Receiver<Option<DWord>>,
+    output: mpsc::Sender<Option<DWord>>,
+    timeout: Option<std::time::Duration>,
+}
+
+impl IntcodeVM {
+    /// Creates an IntcodeVM with given code as the initial state.
+    pub fn new(code: Vec<DWord>) -> IntcodeVM {
+        IntcodeVM::from(Intcode::new(code, Vec::new()))
+    }
+}
+
+impl<M: Memory> From<Intcode<M>> for IntcodeVM<M> {
+    fn from(vm: Intcode<M>) -> Self {
+        let (output, _) = mpsc::channel();
+        let (_, input) = mpsc::channel();
+        IntcodeVM{vm, input, output, timeout: None}
+    }
+}
+
+impl<M: Memory> IntcodeVM<M> {
+    /// Returns current state of the VM.
+    pub fn state(self) -> Vec<DWord> {
+        self.vm.memory()
+    }
+
+    /// Returns current state of the VM.
+    pub fn state_mut(&mut self) -> &mut M {
+        self.vm.memory_mut()
+    }
+
+    /// Returns the underlying stepping VM.
+    pub fn into_inner(self) -> Intcode<M> {
+        self.vm
+    }
+
+    /// Queues a static dataset as this VM's input.
+    /// Useful for when you don't need the input channel mechanic.
+    pub fn simple_input(&mut self, vec: Vec<DWord>) -> &mut Self {
+        self.vm.inputs.extend(vec);
+        self
+    }
+
+    /// Set input channel to read from.
+    pub fn rx(&mut self, rx: mpsc::Receiver<Option<DWord>>) -> &mut Self {
+        self.input = rx;
+        self
+    }
+
+    /// Set output channel to write to.
+    pub fn tx(&mut self, tx: mpsc::Sender<Option<DWord>>) -> &mut Self {
+        self.output = tx;
+        self
+    }
+
+    /// Set timeout for input read.
+    pub fn timeout(&mut self, dur: std::time::Duration) -> &mut Self {
+        self.timeout = Some(dur);
+        self
+    }
+
+    /// Connects this VM's output to other VM's input.
+    pub fn wire<N: Memory>(&mut self, other: &mut IntcodeVM<N>) -> &mut Self {
+        let (tx, rx) = mpsc::channel();
+        self.output = tx;
+        other.input = rx;
+        self
+    }
+
+    /// Creates binding pair for IO for direct communication with the VM.
+    pub fn io(&mut self) -> (mpsc::Sender<Option<DWord>>, mpsc::Receiver<Option<DWord>>) {
+        let (userin, botin) = mpsc::channel();
+        let (botout, userout) = mpsc::channel();
+        self.rx(botin).tx(botout);
+        (userin, userout)
+    }
+
+    /// Executes the VM and collects output into a vec.
+    pub fn execute_and_collect(&mut self) -> Result<Vec<DWord>, IntcodeError> {
+        let (tx, rx) = mpsc::channel();
+        self.tx(tx).execute()?;
+        Ok(rx.try_iter().flatten().collect())
+    }
+
+    /// Runs the VM until it halts, reading input from the channel whenever the queued inputs run out.
+    pub fn execute(&mut self) -> Result<(), IntcodeError> {
+        loop {
+            match self.vm.step() {
+                Ok(Some(value)) => self.output.send(Some(value))?,
+                Ok(None) => (),
+                Err(IntcodeError::NeedsInput) => {
+                    let value = match self.timeout {
+                        Some(dur) => self.input.recv_timeout(dur)?,
+                        None => self.input.recv()?,
+                    };
+                    // A `None` marks the end of input, same as the sender hanging up.
+                    self.vm.inputs.push_back(value.ok_or(IntcodeError::InputClosed)?);
+                }
+                Err(IntcodeError::Halted) => {
+                    // Nobody listening for the halt is fine.
+                    let _ = self.output.send(None);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<M: Memory> Clone for IntcodeVM<M> {
+    /// Warning: Due to single consumer limitation, clone of the VM actually has it's own channel.
+    fn clone(&self) -> Self {
+        let mut vm = IntcodeVM::from(self.vm.clone());
+        vm.timeout = self.timeout;
+        vm
+    }
+}
+
+impl FromStr for IntcodeVM {
+    type Err = IntcodeError;
+
+    fn from_str(s: &str) -> Result<Self, IntcodeError> {
+        Ok(IntcodeVM::new(load::parse(s)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    #[test]
+    fn parse() -> Result<(), IntcodeError> {
+        let vm = IntcodeVM::from_str(QUINE)?;
+        assert_eq!(vm.into_inner(), QUINE.parse::<Intcode>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn basic() -> Result<(), IntcodeError> {
+        let x = IntcodeVM::from_str("3,9,8,9,10,9,4,9,99,-1,8")?
+            .simple_input(vec![8])
+            .timeout(std::time::Duration::from_secs(5))
+            .execute_and_collect()?;
+        assert_eq!(x, vec![1]);
+        Ok(())
+    }
+
+    #[test]
+    fn quine() -> Result<(), IntcodeError> {
+        let x = IntcodeVM::from_str(QUINE)?
+            .timeout(std::time::Duration::from_secs(5))
+            .execute_and_collect()?;
+        assert_eq!(x, vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99]);
+        Ok(())
+    }
+
+    #[test]
+    fn wired_threads() -> Result<(), IntcodeError> {
+        // Doubles its input, two of them chained make a quadrupler.
+        let code = "3,9,1002,9,2,9,4,9,99,0";
+        let mut first = IntcodeVM::from_str(code)?;
+        let mut second = IntcodeVM::from_str(code)?;
+        first.wire(&mut second);
+        let (tx, rx) = mpsc::channel();
+        let (otx, output) = mpsc::channel();
+        first.rx(rx);
+        second.tx(otx);
+
+        let a = std::thread::spawn(move || first.execute());
+        let b = std::thread::spawn(move || second.execute());
+        tx.send(Some(5)).unwrap();
+        assert_eq!(output.recv().unwrap(), Some(20));
+        assert_eq!(output.recv().unwrap(), None);
+        a.join().unwrap()?;
+        b.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn closed_input_fails() {
+        let result = IntcodeVM::from_str("3,0,99").unwrap().execute();
+        assert!(result.is_err());
+    }
+}
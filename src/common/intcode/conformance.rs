@@ -0,0 +1,240 @@
+//! Conformance suite for Intcode engines.
+//!
+//! The suite ships as `.case` files in the `conformance` directory at the root of the crate.
+//! Each file holds any number of cases, each starting with its name in brackets:
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! [relative-write]
+//! code 109,10,21101,2,3,0,4,10,99
+//! data 100: 7, 8          ; cells to set before running, after the code
+//! input 1, 2
+//! output 5                ; every output, in order, none if left out
+//! memory 10: 5            ; cells expected at the end, may be given several times
+//! error needs-input       ; how the run must end, halting if left out
+//! limit 1000              ; instructions to run at most, 100000 by default
+//! ```
+//!
+//! Errors are named `unknown-opcode`, `invalid-mode`, `negative-address`, `immediate-write`,
+//!  `overflow`, `needs-input` and `step-limit` for running out of instructions.
+use super::{
+    engine::Engine,
+    error::{ConformanceError, IntcodeError},
+    fuzz::Factory,
+    load,
+    DWord,
+};
+use std::{
+    fmt, fs,
+    path::Path,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub code: Vec<DWord>,
+    pub inputs: Vec<DWord>,
+    pub outputs: Vec<DWord>,
+    /// Expected cells, as runs starting at an address.
+    pub memory: Vec<(usize, Vec<DWord>)>,
+    /// Name of the error the run must end with, None if it must halt.
+    pub error: Option<String>,
+    pub limit: usize,
+}
+
+/// Name of an error as used in case files.
+pub fn error_name(e: &IntcodeError) -> String {
+    match e {
+        IntcodeError::UnknownOpcode{..} => "unknown-opcode".to_owned(),
+        IntcodeError::InvalidMode{..} => "invalid-mode".to_owned(),
+        IntcodeError::NegativeAddress{..} => "negative-address".to_owned(),
+        IntcodeError::ImmediateWrite{..} => "immediate-write".to_owned(),
+        IntcodeError::Overflow{..} => "overflow".to_owned(),
+        IntcodeError::NeedsInput => "needs-input".to_owned(),
+        e => e.to_string(),
+    }
+}
+
+impl Case {
+    /// Runs the case on `engine`, which must already hold its code and inputs.
+    pub fn check(&self, engine: &mut dyn Engine) -> Result<(), String> {
+        let mut outputs = Vec::new();
+        let mut ended = None;
+        for _ in 0..self.limit {
+            match engine.step() {
+                Ok(output) => outputs.extend(output),
+                Err(IntcodeError::Halted) => {
+                    ended = Some(None);
+                    break;
+                }
+                Err(e) => {
+                    ended = Some(Some(error_name(&e)));
+                    break;
+                }
+            }
+        }
+        let ended = ended.unwrap_or_else(|| Some("step-limit".to_owned()));
+        if ended != self.error {
+            let describe = |end: &Option<String>| end.clone().unwrap_or_else(|| "halt".to_owned());
+            return Err(format!("expected {}, got {} at pc={}", describe(&self.error), describe(&ended), engine.pc()));
+        }
+        if outputs != self.outputs {
+            return Err(format!("expected outputs {:?}, got {:?}", self.outputs, outputs));
+        }
+        for (start, cells) in self.memory.iter() {
+            for (addr, &expected) in (*start..).zip(cells.iter()) {
+                let found = engine.peek(addr);
+                if found != expected {
+                    return Err(format!("expected {} at address {}, found {}", expected, addr, found));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the cases in one file, `file` only names it in errors.
+pub fn parse(file: &str, text: &str) -> Result<Vec<Case>, ConformanceError> {
+    let mut cases: Vec<Case> = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let malformed = |reason: &str| ConformanceError::Malformed{file: file.to_owned(), line, reason: reason.to_owned()};
+        let text = raw.split(';').next().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+        if text.starts_with('[') && text.ends_with(']') {
+            let name = text[1..text.len() - 1].trim().to_owned();
+            if cases.iter().any(|case| case.name == name) {
+                return Err(malformed("case is already defined"));
+            }
+            cases.push(Case{name, code: Vec::new(), inputs: Vec::new(), outputs: Vec::new(), memory: Vec::new(), error: None, limit: 100_000});
+            continue;
+        }
+        let case = cases.last_mut().ok_or_else(|| malformed("expected a [name] first"))?;
+        let mut words = text.splitn(2, char::is_whitespace);
+        let key = words.next().unwrap_or("");
+        let value = words.next().unwrap_or("").trim();
+        let values = |text: &str| if text.is_empty() {
+            Ok(Vec::new())
+        } else {
+            load::parse(text).map_err(|e| malformed(&e.to_string()))
+        };
+        let run = |text: &str| -> Result<(usize, Vec<DWord>), ConformanceError> {
+            let mut parts = text.splitn(2, ':');
+            let start = parts.next().unwrap_or("").trim().parse().map_err(|_| malformed("bad address"))?;
+            Ok((start, values(parts.next().ok_or_else(|| malformed("expected `address: values`"))?)?))
+        };
+        match key {
+            "code" => case.code = values(value)?,
+            "data" => {
+                let (start, cells) = run(value)?;
+                if case.code.len() < start + cells.len() {
+                    case.code.resize(start + cells.len(), 0);
+                }
+                case.code[start..start + cells.len()].copy_from_slice(&cells);
+            }
+            "input" => case.inputs = values(value)?,
+            "output" => case.outputs = values(value)?,
+            "memory" => case.memory.push(run(value)?),
+            "error" => case.error = Some(value.to_owned()),
+            "limit" => case.limit = value.parse().map_err(|_| malformed("bad limit"))?,
+            _ => return Err(malformed("unknown entry")),
+        }
+    }
+    if let Some(case) = cases.iter().find(|case| case.code.is_empty()) {
+        return Err(ConformanceError::Malformed{file: file.to_owned(), line: 0, reason: format!("case `{}` has no code", case.name)});
+    }
+    Ok(cases)
+}
+
+/// Loads every `.case` file in `dir`, in order of their names, each case named `file/case`.
+pub fn load<P: AsRef<Path>>(dir: P) -> Result<Vec<Case>, ConformanceError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "case").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut cases = Vec::new();
+    for path in paths {
+        let file = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        for mut case in parse(&file, &fs::read_to_string(&path)?)? {
+            case.name = format!("{}/{}", file, case.name);
+            cases.push(case);
+        }
+    }
+    Ok(cases)
+}
+
+/// Result of every case on every engine.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Engine, case and why it failed, if it did.
+    pub results: Vec<(&'static str, String, Result<(), String>)>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|(_, _, result)| result.is_ok()).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &(&'static str, String, Result<(), String>)> {
+        self.results.iter().filter(|(_, _, result)| result.is_err())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (engine, case, result) in self.results.iter() {
+            match result {
+                Ok(()) => writeln!(f, "PASS {} {}", engine, case)?,
+                Err(reason) => writeln!(f, "FAIL {} {}: {}", engine, case, reason)?,
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed(), self.results.len() - self.passed())
+    }
+}
+
+/// Runs every case on a fresh instance of every engine.
+pub fn run(cases: &[Case], engines: &[(&'static str, Factory)]) -> Report {
+    let mut report = Report::default();
+    for &(engine, make) in engines.iter() {
+        for case in cases.iter() {
+            let result = case.check(&mut *make(&case.code, &case.inputs));
+            report.results.push((engine, case.name.clone(), result));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fuzz::engines;
+
+    #[test]
+    fn shipped_suite() -> Result<(), ConformanceError> {
+        let cases = load(concat!(env!("CARGO_MANIFEST_DIR"), "/conformance"))?;
+        assert!(cases.len() > 150);
+        let report = run(&cases, &engines());
+        assert_eq!(report.failures().count(), 0, "{}", report);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_failures() -> Result<(), ConformanceError> {
+        let cases = parse("inline", "[wrong]\ncode 104,1,99\noutput 2\n[loops]\ncode 1105,1,0\nlimit 10\n")?;
+        let report = run(&cases, &engines()[..1]);
+        assert_eq!(report.to_string(), "\
+FAIL paged wrong: expected outputs [2], got [1]
+FAIL paged loops: expected halt, got step-limit at pc=0
+0 passed, 2 failed");
+        match parse("inline", "[a]\ncode 1\nflavour sweet\n") {
+            Err(ConformanceError::Malformed{line: 3, ..}) => Ok(()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
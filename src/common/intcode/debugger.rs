@@ -0,0 +1,352 @@
+//! Breakpoints, watchpoints and stepping on top of `Intcode`, plus a tiny line-oriented REPL.
+use super::{
+    disasm::{disassemble, Line},
+    error::IntcodeError,
+    instruction::Op,
+    memory::{Memory, Paged},
+    vm::{Intcode, State},
+    DWord,
+};
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+};
+
+/// Why the debugger stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Executed an instruction and nothing interesting happened.
+    Stepped,
+    /// Reached a breakpoint, the instruction at it hasn't been executed yet.
+    Breakpoint(usize),
+    /// A watched cell was written to by the instruction at `pc`.
+    Watchpoint{addr: usize, pc: usize, old: DWord, new: DWord},
+    Output(DWord),
+    /// Reached an input instruction, it hasn't been executed yet.
+    Input(usize),
+    /// Input instruction executed with an empty input queue.
+    NeedsInput,
+    Halted,
+}
+
+#[derive(Debug, Clone)]
+pub struct Debugger<M: Memory = Paged> {
+    vm: Intcode<M>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    outputs: Vec<DWord>,
+}
+
+impl<M: Memory> From<Intcode<M>> for Debugger<M> {
+    fn from(vm: Intcode<M>) -> Self {
+        Self{vm, breakpoints: BTreeSet::new(), watchpoints: BTreeSet::new(), outputs: Vec::new()}
+    }
+}
+
+impl<M: Memory> Debugger<M> {
+    pub fn vm(&self) -> &Intcode<M> {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Intcode<M> {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> Intcode<M> {
+        self.vm
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.insert(addr)
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &usize> {
+        self.watchpoints.iter()
+    }
+
+    /// Takes every value output since the last call.
+    pub fn take_outputs(&mut self) -> Vec<DWord> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.vm.pc()
+    }
+
+    pub fn base(&self) -> DWord {
+        self.vm.base()
+    }
+
+    pub fn state(&self) -> State {
+        self.vm.state()
+    }
+
+    /// Returns `len` memory cells starting at `from`, unset cells read as 0.
+    /// Cells past the end of the address space are left out.
+    pub fn peek(&self, from: usize, len: usize) -> Vec<DWord> {
+        (from..from.saturating_add(len)).map(|addr| self.vm[addr]).collect()
+    }
+
+    /// Disassembles `count` instructions starting at `from`.
+    pub fn list(&self, from: usize, count: usize) -> Vec<Line> {
+        // Instructions are at most 4 words long, so this window always fits `count` of them.
+        let window = self.peek(from, count.saturating_mul(4));
+        disassemble(&window).into_iter()
+            .take(count)
+            .map(|line| match line {
+                Line::Instruction(addr, ins) => Line::Instruction(addr + from, ins),
+                Line::Data(addr, word) => Line::Data(addr + from, word),
+            })
+            .collect()
+    }
+
+    /// Executes exactly one instruction.
+    pub fn step(&mut self) -> Result<Event, IntcodeError> {
+        let pc = self.vm.pc();
+        let target = match self.vm.parse_opcode() {
+            Ok((args, op)) => Op::from_code(op as DWord)
+                .and_then(Op::write_param)
+                .map(|i| args[i])
+                .filter(|addr| self.watchpoints.contains(addr)),
+            Err(_) => None,
+        };
+        let old = target.map(|addr| self.vm[addr]);
+        let output = match self.vm.step() {
+            Ok(output) => output,
+            Err(IntcodeError::Halted) => return Ok(Event::Halted),
+            Err(IntcodeError::NeedsInput) => return Ok(Event::NeedsInput),
+            Err(e) => return Err(e),
+        };
+        self.outputs.extend(output);
+        Ok(match (target, old, output) {
+            (Some(addr), Some(old), _) => Event::Watchpoint{addr, pc, old, new: self.vm[addr]},
+            (_, _, Some(value)) => Event::Output(value),
+            _ => Event::Stepped,
+        })
+    }
+
+    /// Runs until a breakpoint, watchpoint, halt or missing input, outputs are collected on the way.
+    pub fn cont(&mut self) -> Result<Event, IntcodeError> {
+        self.run(|_| false, false)
+    }
+
+    /// Runs like `cont`, but also stops right after an output.
+    pub fn run_until_output(&mut self) -> Result<Event, IntcodeError> {
+        self.run(|event| matches!(event, Event::Output(_)), false)
+    }
+
+    /// Runs like `cont`, but also stops in front of the next input instruction.
+    pub fn run_until_input(&mut self) -> Result<Event, IntcodeError> {
+        self.run(|_| false, true)
+    }
+
+    /// Always executes at least one instruction, so continuing from a breakpoint makes progress.
+    fn run<F: Fn(&Event) -> bool>(&mut self, stop: F, stop_on_input: bool) -> Result<Event, IntcodeError> {
+        loop {
+            let event = self.step()?;
+            match event {
+                Event::Stepped | Event::Output(_) if !stop(&event) => (),
+                _ => return Ok(event),
+            }
+            let pc = self.vm.pc();
+            if self.breakpoints.contains(&pc) {
+                return Ok(Event::Breakpoint(pc));
+            }
+            if stop_on_input && self.vm[pc] % 100 == Op::In.code() {
+                return Ok(Event::Input(pc));
+            }
+        }
+    }
+
+    /// Reads commands line by line from `input` and writes results to `output`, until `quit` or EOF.
+    ///
+    /// Pass `io::stdin().lock()` and `io::stdout()` to debug interactively. Type `help` for commands.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let cmd = match words.next() {
+                Some(cmd) => cmd,
+                None => continue,
+            };
+            // Pushed inputs may be negative, every other argument is an address or a count.
+            let parsed = if cmd == "push" {
+                words.map(str::parse).collect::<Result<_, _>>().map(|values| (Vec::new(), values))
+            } else {
+                words.map(str::parse).collect::<Result<_, _>>().map(|args| (args, Vec::new()))
+            };
+            let (args, values): (Vec<usize>, Vec<DWord>) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    writeln!(output, "bad argument: {}", e)?;
+                    continue;
+                }
+            };
+            let arg = |i: usize, default: usize| args.get(i).copied().unwrap_or(default);
+            let result = match cmd {
+                "s" | "step" => {
+                    let mut result = Ok(Event::Stepped);
+                    for _ in 0..arg(0, 1) {
+                        result = self.step();
+                        if result.as_ref().map(|e| *e != Event::Stepped).unwrap_or(true) {
+                            break;
+                        }
+                    }
+                    Some(result)
+                }
+                "c" | "continue" => Some(self.cont()),
+                "o" | "out" => Some(self.run_until_output()),
+                "i" | "in" => Some(self.run_until_input()),
+                "b" | "break" => {
+                    self.add_breakpoint(arg(0, self.pc()));
+                    None
+                }
+                "db" | "delete" => {
+                    self.remove_breakpoint(arg(0, self.pc()));
+                    None
+                }
+                "w" | "watch" => {
+                    args.iter().for_each(|&a| { self.add_watchpoint(a); });
+                    None
+                }
+                "dw" | "unwatch" => {
+                    args.iter().for_each(|&a| { self.remove_watchpoint(a); });
+                    None
+                }
+                "push" => {
+                    self.vm.inputs.extend(values);
+                    None
+                }
+                "r" | "regs" => {
+                    writeln!(output, "pc={} base={} state={:?} inputs={:?}",
+                        self.pc(), self.base(), self.state(), self.vm.inputs)?;
+                    None
+                }
+                "x" | "mem" => {
+                    let from = arg(0, self.pc());
+                    for (i, chunk) in self.peek(from, arg(1, 8)).chunks(8).enumerate() {
+                        let words: Vec<_> = chunk.iter().map(|w| w.to_string()).collect();
+                        writeln!(output, "{:04}: {}", from + i * 8, words.join(" "))?;
+                    }
+                    None
+                }
+                "l" | "list" => {
+                    for line in self.list(arg(0, self.pc()), arg(1, 5)) {
+                        writeln!(output, "{}", line)?;
+                    }
+                    None
+                }
+                "q" | "quit" => return Ok(()),
+                "h" | "help" => {
+                    writeln!(output, "step [n], continue, out, in, break [addr], delete [addr], \
+                        watch addr.., unwatch addr.., push value.., regs, mem [addr] [len], \
+                        list [addr] [n], quit")?;
+                    None
+                }
+                _ => {
+                    writeln!(output, "unknown command `{}`, try `help`", cmd)?;
+                    None
+                }
+            };
+            for value in self.take_outputs() {
+                writeln!(output, "output {}", value)?;
+            }
+            match result {
+                Some(Ok(event)) => writeln!(output, "{:?} at pc={}", event, self.pc())?,
+                Some(Err(e)) => writeln!(output, "error: {}", e)?,
+                None => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    #[test]
+    fn breakpoints_and_outputs() -> Result<(), IntcodeError> {
+        let mut dbg = Debugger::from(QUINE.parse::<Intcode>()?);
+        dbg.add_breakpoint(4);
+        assert_eq!(dbg.cont()?, Event::Breakpoint(4));
+        assert_eq!(dbg.take_outputs(), vec![109]);
+        assert_eq!(dbg.run_until_output()?, Event::Output(1));
+        assert_eq!(dbg.peek(100, 2), vec![1, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn watchpoints() -> Result<(), IntcodeError> {
+        let mut dbg = Debugger::from(Intcode::new(vec![3,9,8,9,10,9,4,9,99,-1,8], vec![8]));
+        dbg.add_watchpoint(9);
+        assert_eq!(dbg.cont()?, Event::Watchpoint{addr: 9, pc: 0, old: -1, new: 8});
+        assert_eq!(dbg.cont()?, Event::Watchpoint{addr: 9, pc: 2, old: 8, new: 1});
+        assert_eq!(dbg.cont()?, Event::Halted);
+        Ok(())
+    }
+
+    #[test]
+    fn until_input() -> Result<(), IntcodeError> {
+        let mut dbg = Debugger::from("104,7,3,0,3,0,99".parse::<Intcode>()?);
+        assert_eq!(dbg.run_until_input()?, Event::Input(2));
+        assert_eq!(dbg.run_until_input()?, Event::NeedsInput);
+        dbg.vm_mut().inputs.push_back(1);
+        assert_eq!(dbg.run_until_input()?, Event::Input(4));
+        Ok(())
+    }
+
+    #[test]
+    fn repl_session() {
+        let mut dbg = Debugger::from(QUINE.parse::<Intcode>().unwrap());
+        let mut out = Vec::new();
+        dbg.repl("b 12\nc\nc\nr\nl 0 2\nx 100 2\nquit\nc\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+output 109
+Breakpoint(12) at pc=12
+output 1
+Breakpoint(12) at pc=12
+pc=12 base=2 state=Ready inputs=[]
+0000: arb #1
+0002: out [rb-1]
+0100: 2 0
+");
+    }
+
+    #[test]
+    fn repl_bad_arguments() {
+        let mut dbg = Debugger::from(QUINE.parse::<Intcode>().unwrap());
+        let mut out = Vec::new();
+        dbg.repl("x -1
+w -5
+l 0 -2
+push -3
+x 18446744073709551614 4
+r
+".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "bad argument: invalid digit found in string
+bad argument: invalid digit found in string
+bad argument: invalid digit found in string
+18446744073709551614: 0
+pc=0 base=0 state=Ready inputs=[-3]
+");
+    }
+}
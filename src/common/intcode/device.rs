@@ -0,0 +1,105 @@
+//! Hardware models driven by an Intcode program.
+//!
+//! A `Device` gets every word the program outputs and supplies input whenever the program runs
+//!  out of it. `Runner` couples a VM to a device and runs them until either one stops, so a
+//!  puzzle only has to model its device.
+use super::{
+    engine::Engine,
+    error::IntcodeError,
+    memory::Paged,
+    vm::Intcode,
+    DWord,
+};
+
+/// Whether the runner should go on after a device handled an output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Why `Runner::run` returned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Finish {
+    Halted,
+    /// The device asked to stop, running again resumes where it left off.
+    Stopped,
+}
+
+pub trait Device {
+    /// Supplies the next input word once the program needs one, None stops the runner.
+    fn input(&mut self) -> Option<DWord>;
+
+    /// Receives the next output word of the program.
+    fn output(&mut self, value: DWord) -> Control;
+}
+
+#[derive(Clone, Debug)]
+pub struct Runner<D: Device, E: Engine = Intcode<Paged>> {
+    pub vm: E,
+    pub device: D,
+}
+
+impl<D: Device, E: Engine> Runner<D, E> {
+    pub fn new(vm: E, device: D) -> Self {
+        Runner{vm, device}
+    }
+
+    /// Runs the VM, feeding its outputs to the device and asking it for input when there's none.
+    pub fn run(&mut self) -> Result<Finish, IntcodeError> {
+        loop {
+            match self.vm.step() {
+                Ok(Some(value)) => if self.device.output(value) == Control::Stop {
+                    return Ok(Finish::Stopped);
+                },
+                Ok(None) => (),
+                Err(IntcodeError::NeedsInput) => match self.device.input() {
+                    Some(value) => self.vm.inputs_mut().push_back(value),
+                    None => return Ok(Finish::Stopped),
+                },
+                Err(IntcodeError::Halted) => return Ok(Finish::Halted),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every input with the sum of the outputs so far and stops after `limit` outputs.
+    #[derive(Default)]
+    struct Summer {
+        outputs: Vec<DWord>,
+        limit: usize,
+    }
+
+    impl Device for Summer {
+        fn input(&mut self) -> Option<DWord> {
+            Some(self.outputs.iter().sum::<DWord>() + 1)
+        }
+
+        fn output(&mut self, value: DWord) -> Control {
+            self.outputs.push(value);
+            if self.outputs.len() == self.limit { Control::Stop } else { Control::Continue }
+        }
+    }
+
+    #[test]
+    fn drives_the_program() -> Result<(), IntcodeError> {
+        // Echoes its input forever.
+        let echo = "3,7,4,7,1105,1,0,0".parse::<Intcode>()?;
+        let mut runner = Runner::new(echo, Summer{outputs: Vec::new(), limit: 3});
+        assert_eq!(runner.run()?, Finish::Stopped);
+        assert_eq!(runner.device.outputs, vec![1, 2, 4]);
+        runner.device.limit = 5;
+        assert_eq!(runner.run()?, Finish::Stopped);
+        assert_eq!(runner.device.outputs, vec![1, 2, 4, 8, 16]);
+
+        let mut runner = Runner::new("3,0,4,0,99".parse::<Intcode>()?, Summer::default());
+        assert_eq!(runner.run()?, Finish::Halted);
+        assert_eq!(runner.device.outputs, vec![1]);
+        Ok(())
+    }
+}
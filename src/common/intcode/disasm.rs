@@ -0,0 +1,88 @@
+//! Turns Intcode memory into a readable assembly listing.
+use super::{instruction::Instruction, DWord};
+use std::fmt;
+
+/// One entry of the listing, either a decoded instruction or a raw data word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Instruction(usize, Instruction),
+    Data(usize, DWord),
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction(addr, _) => *addr,
+            Line::Data(addr, _) => *addr,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction(addr, ins) => write!(f, "{:04}: {}", addr, ins),
+            Line::Data(addr, word) => write!(f, "{:04}: .data {}", addr, word),
+        }
+    }
+}
+
+/// Walks memory from address 0, decoding instructions linearly.
+/// Words that don't decode as a valid instruction are emitted as data and skipped over.
+pub fn disassemble(memory: &[DWord]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        match Instruction::decode(memory, addr) {
+            Some(ins) => {
+                let size = ins.size();
+                lines.push(Line::Instruction(addr, ins));
+                addr += size;
+            }
+            None => {
+                lines.push(Line::Data(addr, memory[addr]));
+                addr += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// Renders the whole program as a listing, one line per instruction or data word.
+pub fn listing(memory: &[DWord]) -> String {
+    disassemble(memory).iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day5_compare() {
+        let listing = listing(&[3,9,8,9,10,9,4,9,99,-1,8]);
+        assert_eq!(listing, "\
+0000: in [9]
+0002: eq [9], [10], [9]
+0006: out [9]
+0008: hlt
+0009: .data -1
+0010: .data 8
+");
+    }
+
+    #[test]
+    fn relative_and_data() {
+        let lines = disassemble(&[109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99]);
+        let rendered: Vec<_> = lines.iter().map(|l| l.to_string()).collect();
+        assert_eq!(rendered, vec![
+            "0000: arb #1",
+            "0002: out [rb-1]",
+            "0004: add [100], #1, [100]",
+            "0008: eq [100], #16, [101]",
+            "0012: jf [101], #0",
+            "0015: hlt",
+        ]);
+    }
+}
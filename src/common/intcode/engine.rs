@@ -0,0 +1,69 @@
+//! Common interface of the Intcode execution engines.
+use super::{
+    error::IntcodeError,
+    memory::Memory,
+    vm::{Intcode, State},
+    DWord,
+};
+use std::collections::VecDeque;
+
+/// Something that executes Intcode with the semantics of `Intcode::step`.
+pub trait Engine {
+    fn step(&mut self) -> Result<Option<DWord>, IntcodeError>;
+
+    fn state(&self) -> State;
+
+    fn pc(&self) -> usize;
+
+    /// Number of instructions executed so far.
+    fn executed(&self) -> u64;
+
+    /// Value of the memory cell at `addr`.
+    fn peek(&self, addr: usize) -> DWord;
+
+    fn inputs_mut(&mut self) -> &mut VecDeque<DWord>;
+}
+
+impl<M: Memory> Engine for Intcode<M> {
+    fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
+        Intcode::step(self)
+    }
+
+    fn state(&self) -> State {
+        Intcode::state(self)
+    }
+
+    fn pc(&self) -> usize {
+        Intcode::pc(self)
+    }
+
+    fn executed(&self) -> u64 {
+        Intcode::executed(self)
+    }
+
+    fn peek(&self, addr: usize) -> DWord {
+        self[addr]
+    }
+
+    fn inputs_mut(&mut self) -> &mut VecDeque<DWord> {
+        &mut self.inputs
+    }
+}
+
+/// Something that steps an `Intcode` on its behalf, watching what each instruction does.
+pub trait Observer {
+    /// Same as `Intcode::step`, plus whatever the observer records.
+    fn step<M: Memory>(&mut self, vm: &mut Intcode<M>) -> Result<Option<DWord>, IntcodeError>;
+
+    /// Runs the VM until it halts, collecting its output. Stops with an error on anything else.
+    fn run<M: Memory>(&mut self, vm: &mut Intcode<M>) -> Result<Vec<DWord>, IntcodeError> {
+        let mut outputs = Vec::new();
+        loop {
+            match self.step(vm) {
+                Ok(output) => outputs.extend(output),
+                Err(IntcodeError::Halted) => return Ok(outputs),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
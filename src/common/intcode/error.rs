@@ -1,14 +1,61 @@
+use std::fmt;
+use std::io;
 use std::num::ParseIntError;
 use std::sync::mpsc;
-use super::DWord;
+use super::{budget::Limit, instruction::Op, DWord};
+
+/// Where and when a runtime error happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub pc: usize,
+    /// Raw instruction word at `pc`.
+    pub word: DWord,
+    /// Mode digit of every parameter of the instruction.
+    pub modes: Vec<DWord>,
+    pub base: DWord,
+    /// Instructions executed before the faulting one.
+    pub executed: u64,
+    /// Address of the first cell in `memory`.
+    pub start: usize,
+    /// Memory around `pc` at the time of the error.
+    pub memory: Vec<DWord>,
+}
+
+impl Fault {
+    /// Decodes the modes from `word`, `memory` holds cells starting at `start`.
+    pub(crate) fn new(pc: usize, word: DWord, base: DWord, executed: u64, start: usize, memory: Vec<DWord>) -> Box<Fault> {
+        let arity = Op::from_code(word % 100).map(Op::arity).unwrap_or(0);
+        let modes = (0..arity as u32).map(|i| word / 10i64.pow(i + 2) % 10).collect();
+        Box::new(Fault{pc, word, modes, base, executed, start, memory})
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modes = self.modes.iter().map(DWord::to_string).collect::<Vec<_>>().join(",");
+        write!(
+            f,
+            "at pc={} word={} modes=[{}] rb={} after {} instructions\n{:04}:",
+            self.pc, self.word, modes, self.base, self.executed, self.start,
+        )?;
+        for (addr, value) in (self.start..).zip(self.memory.iter()) {
+            if addr == self.pc {
+                write!(f, " >{}<", value)?;
+            } else {
+                write!(f, " {}", value)?;
+            }
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug, Fail)]
 pub enum IntcodeError {
-    #[fail(display = "unknown opcode `{}` met", opcode)]
-    UnknownOpcode {opcode: DWord},
+    #[fail(display = "unknown opcode `{}` {}", opcode, fault)]
+    UnknownOpcode{opcode: DWord, fault: Box<Fault>},
 
-    #[fail(display = "code parse failed")]
-    BadCode(#[fail(cause)] ParseIntError),
+    #[fail(display = "code loading failed: {}", _0)]
+    BadCode(#[fail(cause)] LoadError),
 
     #[fail(display = "wrapped none")]
     NoneError(std::option::NoneError),
@@ -16,44 +63,68 @@ pub enum IntcodeError {
     #[fail(display = "VM is halted")]
     Halted,
 
-    #[fail(display = "invalid mode {}", mode)]
-    InvalidMode{mode: DWord},
+    #[fail(display = "invalid mode {} of parameter {} {}", mode, param, fault)]
+    InvalidMode{mode: DWord, param: usize, fault: Box<Fault>},
+
+    #[fail(display = "negative address {} {}", addr, fault)]
+    NegativeAddress{addr: DWord, fault: Box<Fault>},
+
+    #[fail(display = "parameter {} is written to in immediate mode {}", param, fault)]
+    ImmediateWrite{param: usize, fault: Box<Fault>},
+
+    #[fail(display = "arithmetic overflow {}", fault)]
+    Overflow{fault: Box<Fault>},
+
+    #[fail(display = "budget of {} exhausted {}", limit, fault)]
+    BudgetExhausted{limit: Limit, fault: Box<Fault>},
 
     #[fail(display = "input required")]
     NeedsInput,
 
-    // Legacy stuff cause I can't be hecked to rewrite old days...
+    #[fail(display = "trace write failed")]
+    TraceWriteFailed(#[fail(cause)] io::Error),
 
-    #[fail(display = "input read failed")]
-    LegacyInputReadFailed(#[fail(cause)] mpsc::RecvError),
+    /// The program wants input, but no more is coming.
+    #[fail(display = "input closed")]
+    InputClosed,
 
     #[fail(display = "input read timed out")]
-    LegacyInputReadTimedout(#[fail(cause)] mpsc::RecvTimeoutError),
+    InputTimedOut,
 
-    #[fail(display = "output write failed")]
-    LegacyOutputWriteFailed(#[fail(cause)] mpsc::SendError<DWord>),
+    /// The program produced output, but nobody is listening anymore.
+    #[fail(display = "output closed")]
+    OutputClosed,
 }
 
 impl std::convert::From<mpsc::RecvError> for IntcodeError {
-    fn from(x: mpsc::RecvError) -> Self {
-        IntcodeError::LegacyInputReadFailed(x)
+    fn from(_: mpsc::RecvError) -> Self {
+        IntcodeError::InputClosed
     }
 }
 
 impl std::convert::From<mpsc::RecvTimeoutError> for IntcodeError {
     fn from(x: mpsc::RecvTimeoutError) -> Self {
-        IntcodeError::LegacyInputReadTimedout(x)
+        match x {
+            mpsc::RecvTimeoutError::Timeout => IntcodeError::InputTimedOut,
+            mpsc::RecvTimeoutError::Disconnected => IntcodeError::InputClosed,
+        }
+    }
+}
+
+impl<T> std::convert::From<mpsc::SendError<T>> for IntcodeError {
+    fn from(_: mpsc::SendError<T>) -> Self {
+        IntcodeError::OutputClosed
     }
 }
 
-impl std::convert::From<mpsc::SendError<DWord>> for IntcodeError {
-    fn from(x: mpsc::SendError<DWord>) -> Self {
-        IntcodeError::LegacyOutputWriteFailed(x)
+impl std::convert::From<io::Error> for IntcodeError {
+    fn from(x: io::Error) -> Self {
+        IntcodeError::TraceWriteFailed(x)
     }
 }
 
-impl std::convert::From<ParseIntError> for IntcodeError {
-    fn from(x: ParseIntError) -> Self {
+impl std::convert::From<LoadError> for IntcodeError {
+    fn from(x: LoadError) -> Self {
         IntcodeError::BadCode(x)
     }
 }
@@ -63,3 +134,132 @@ impl std::convert::From<std::option::NoneError> for IntcodeError {
         IntcodeError::NoneError(x)
     }
 }
+
+#[derive(Debug, Fail)]
+pub enum AsmError {
+    #[fail(display = "line {}: unknown mnemonic `{}`", line, mnemonic)]
+    UnknownMnemonic{line: usize, mnemonic: String},
+
+    #[fail(display = "line {}: `{}` takes {} operands, got {}", line, mnemonic, expected, got)]
+    WrongArity{line: usize, mnemonic: String, expected: usize, got: usize},
+
+    #[fail(display = "line {}: bad operand `{}`", line, operand)]
+    BadOperand{line: usize, operand: String},
+
+    #[fail(display = "line {}: operand {} of `{}` is written to and can't be immediate", line, index, mnemonic)]
+    ImmediateWrite{line: usize, mnemonic: String, index: usize},
+
+    #[fail(display = "line {}: unknown label `{}`", line, label)]
+    UnknownLabel{line: usize, label: String},
+
+    #[fail(display = "line {}: label `{}` is already defined", line, label)]
+    DuplicateLabel{line: usize, label: String},
+
+    #[fail(display = "line {}: expected address {}, assembled to {}", line, expected, actual)]
+    AddressMismatch{line: usize, expected: usize, actual: usize},
+}
+
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    #[fail(display = "not an intcode snapshot")]
+    NotASnapshot,
+
+    #[fail(display = "snapshot version {} is not supported, expected {}", found, expected)]
+    UnsupportedVersion{found: String, expected: u32},
+
+    #[fail(display = "line {}: {}", line, reason)]
+    Malformed{line: usize, reason: String},
+
+    #[fail(display = "missing `{}` entry", field)]
+    Missing{field: &'static str},
+
+    #[fail(display = "snapshot io failed")]
+    Io(#[fail(cause)] io::Error),
+}
+
+impl std::convert::From<io::Error> for SnapshotError {
+    fn from(x: io::Error) -> Self {
+        SnapshotError::Io(x)
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum LoadError {
+    #[fail(display = "no code to load")]
+    Empty,
+
+    #[fail(display = "value {} at byte {} is missing", index, offset)]
+    MissingValue{index: usize, offset: usize},
+
+    #[fail(display = "value {} at byte {} is not a number: `{}`", index, offset, token)]
+    BadToken{index: usize, offset: usize, token: String, #[fail(cause)] cause: ParseIntError},
+
+    #[fail(display = "not a binary intcode program")]
+    NotBinary,
+
+    #[fail(display = "neither a binary intcode program nor text")]
+    NotText,
+
+    #[fail(display = "value at byte {} is cut off", offset)]
+    Truncated{offset: usize},
+
+    #[fail(display = "value at byte {} doesn't fit into 64 bits", offset)]
+    TooLarge{offset: usize},
+
+    #[fail(display = "program io failed")]
+    Io(#[fail(cause)] io::Error),
+}
+
+impl std::convert::From<io::Error> for LoadError {
+    fn from(x: io::Error) -> Self {
+        LoadError::Io(x)
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum PatchError {
+    #[fail(display = "line {}: can't parse `{}`", line, text)]
+    Syntax{line: usize, text: String},
+
+    #[fail(display = "line {}: patch set `{}` is already defined", line, name)]
+    DuplicateName{line: usize, name: String},
+
+    #[fail(display = "no patch set named `{}`", name)]
+    UnknownName{name: String},
+
+    #[fail(display = "expected {} at address {}, found {}", expected, addr, found)]
+    Mismatch{addr: usize, expected: DWord, found: DWord},
+
+    #[fail(display = "no instruction at address {} to replace", addr)]
+    NotAnInstruction{addr: usize},
+
+    #[fail(display = "instruction at address {} is {} cells long, too short for a jump", addr, size)]
+    TooShortForJump{addr: usize, size: usize},
+
+    #[fail(display = "can't fill {}..{} with no-ops", from, to)]
+    BadRange{from: usize, to: usize},
+
+    #[fail(display = "patch io failed")]
+    Io(#[fail(cause)] io::Error),
+}
+
+impl std::convert::From<io::Error> for PatchError {
+    fn from(x: io::Error) -> Self {
+        PatchError::Io(x)
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ConformanceError {
+    #[fail(display = "{} line {}: {}", file, line, reason)]
+    Malformed{file: String, line: usize, reason: String},
+
+    #[fail(display = "conformance suite io failed")]
+    Io(#[fail(cause)] io::Error),
+}
+
+impl std::convert::From<io::Error> for ConformanceError {
+    fn from(x: io::Error) -> Self {
+        ConformanceError::Io(x)
+    }
+}
@@ -0,0 +1,335 @@
+//! Static control-flow graph of an Intcode image.
+//!
+//! Code is found by following every reachable instruction from address 0, so data mixed into the
+//!  program stays out of the graph. Jumps whose target comes from memory can't be followed and
+//!  end up as edges without a target.
+//!
+//! Compiled Intcode calls functions by storing the return address in a relative-mode slot and
+//!  jumping unconditionally, functions then move the relative base for their frame and return
+//!  by jumping through `[rb]`. Calls recognized that way split the graph into functions.
+use super::{
+    instruction::{Instruction, Mode, Op},
+    DWord,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Straight into the next block.
+    Fallthrough,
+    /// Unconditional jump.
+    Jump,
+    /// Conditional jump, taken.
+    Branch,
+    /// From a call site to the called function.
+    Call,
+    /// From a call site to where the call returns.
+    AfterCall,
+    /// Return through the stored return address, target unknown.
+    Return,
+    /// Jump to an address read from memory, target unknown.
+    Computed,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: usize,
+    /// Start of the target block, `None` when it can't be known statically.
+    pub to: Option<usize>,
+    pub kind: EdgeKind,
+}
+
+/// How a block ends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exit {
+    Halt,
+    Jump,
+    /// Runs into the next block.
+    Fallthrough,
+    /// Runs into words that aren't a valid instruction.
+    Invalid,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub exit: Exit,
+}
+
+impl Block {
+    /// Address right after the last instruction.
+    pub fn end(&self) -> usize {
+        self.instructions.last().map(|(addr, ins)| addr + ins.size()).unwrap_or(self.start)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub entry: usize,
+    /// Size of the frame allocated by an `arb` at the entry.
+    pub frame: Option<DWord>,
+    /// Starts of the blocks reachable from the entry without following calls.
+    pub blocks: BTreeSet<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub edges: Vec<Edge>,
+    /// Keyed by entry, address 0 counts as a function too.
+    pub functions: BTreeMap<usize, Function>,
+}
+
+/// Value of an instruction storing a constant into a relative-mode slot, like pushing a return address.
+fn relative_store(ins: &Instruction) -> Option<DWord> {
+    let (a, b, to) = match ins.params.as_slice() {
+        [a, b, to] if a.mode == Mode::Immediate && b.mode == Mode::Immediate => (a.value, b.value, to),
+        _ => return None,
+    };
+    if to.mode != Mode::Relative {
+        return None;
+    }
+    match ins.op {
+        Op::Add => a.checked_add(b),
+        Op::Mul => a.checked_mul(b),
+        _ => None,
+    }
+}
+
+/// Whether a conditional jump always or never jumps, `None` if it depends on memory.
+fn condition(ins: &Instruction) -> Option<bool> {
+    let cond = ins.params[0];
+    if cond.mode != Mode::Immediate {
+        return None;
+    }
+    Some((cond.value != 0) == (ins.op == Op::JumpIfTrue))
+}
+
+impl Cfg {
+    pub fn build(code: &[DWord]) -> Cfg {
+        // Find every reachable instruction, and where blocks start.
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+        let mut work = vec![0];
+        leaders.insert(0);
+        while let Some(start) = work.pop() {
+            let mut pc = start;
+            let mut stored = None;
+            while !instructions.contains_key(&pc) {
+                let ins = match Instruction::decode(code, pc) {
+                    Some(ins) => ins,
+                    None => break,
+                };
+                let next = pc + ins.size();
+                let op = ins.op;
+                let mut targets = Vec::new();
+                if let Op::JumpIfTrue | Op::JumpIfFalse = op {
+                    let always = condition(&ins);
+                    let target = ins.params[1];
+                    if always != Some(false) && target.mode == Mode::Immediate && target.value >= 0 {
+                        targets.push(target.value as usize);
+                        if always == Some(true) && stored == Some(next as DWord) {
+                            calls.insert(pc);
+                            targets.push(next);
+                        }
+                    }
+                    if always != Some(true) {
+                        targets.push(next);
+                    }
+                }
+                stored = relative_store(&ins).or(stored);
+                instructions.insert(pc, ins);
+                if op == Op::Halt {
+                    break;
+                }
+                if let Op::JumpIfTrue | Op::JumpIfFalse = op {
+                    for target in targets {
+                        leaders.insert(target);
+                        work.push(target);
+                    }
+                    break;
+                }
+                pc = next;
+            }
+        }
+
+        // Split the instructions into blocks and connect them.
+        let mut blocks = BTreeMap::new();
+        let mut edges = Vec::new();
+        for &start in leaders.iter().filter(|addr| instructions.contains_key(addr)) {
+            let mut block = Block{start, instructions: Vec::new(), exit: Exit::Invalid};
+            let mut pc = start;
+            while let Some(ins) = instructions.get(&pc) {
+                block.instructions.push((pc, ins.clone()));
+                let next = pc + ins.size();
+                let edge = |to, kind| Edge{from: start, to, kind};
+                match ins.op {
+                    Op::Halt => block.exit = Exit::Halt,
+                    Op::JumpIfTrue | Op::JumpIfFalse => {
+                        block.exit = Exit::Jump;
+                        let always = condition(ins);
+                        let target = ins.params[1];
+                        let known = Some(target.value as usize).filter(|_| target.mode == Mode::Immediate && target.value >= 0);
+                        match (always, known) {
+                            (Some(false), _) => (),
+                            (Some(true), Some(_)) if calls.contains(&pc) => {
+                                edges.push(edge(known, EdgeKind::Call));
+                                edges.push(edge(Some(next), EdgeKind::AfterCall));
+                            }
+                            (Some(true), Some(_)) => edges.push(edge(known, EdgeKind::Jump)),
+                            (None, Some(_)) => edges.push(edge(known, EdgeKind::Branch)),
+                            (Some(true), None) if target.mode == Mode::Relative => edges.push(edge(None, EdgeKind::Return)),
+                            (_, None) => edges.push(edge(None, EdgeKind::Computed)),
+                        }
+                        if always != Some(true) && instructions.contains_key(&next) {
+                            edges.push(edge(Some(next), EdgeKind::Fallthrough));
+                        }
+                    }
+                    _ if leaders.contains(&next) && instructions.contains_key(&next) => {
+                        block.exit = Exit::Fallthrough;
+                        edges.push(edge(Some(next), EdgeKind::Fallthrough));
+                    }
+                    _ => {
+                        pc = next;
+                        continue;
+                    }
+                }
+                break;
+            }
+            blocks.insert(start, block);
+        }
+
+        // Every call target, plus the program entry, starts a function.
+        let mut entries: BTreeSet<usize> = edges.iter()
+            .filter(|e| e.kind == EdgeKind::Call)
+            .filter_map(|e| e.to)
+            .collect();
+        entries.insert(0);
+        let functions = entries.into_iter()
+            .filter(|entry| blocks.contains_key(entry))
+            .map(|entry| {
+                let mut reached = BTreeSet::new();
+                let mut work = vec![entry];
+                while let Some(block) = work.pop() {
+                    if reached.insert(block) {
+                        work.extend(edges.iter()
+                            .filter(|e| e.from == block && e.kind != EdgeKind::Call)
+                            .filter_map(|e| e.to));
+                    }
+                }
+                let frame = match blocks[&entry].instructions.first() {
+                    Some((_, ins)) if ins.op == Op::AdjustBase && ins.params[0].mode == Mode::Immediate => {
+                        Some(ins.params[0].value)
+                    }
+                    _ => None,
+                };
+                (entry, Function{entry, frame, blocks: reached})
+            })
+            .collect();
+
+        Cfg{blocks, edges, functions}
+    }
+
+    /// Edges leaving the block starting at `block`.
+    pub fn successors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.from == block)
+    }
+
+    /// Renders the graph in Graphviz DOT, blocks grouped by the first function they belong to.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph intcode {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+        let mut placed = BTreeSet::new();
+        for function in self.functions.values() {
+            writeln!(out, "    subgraph cluster_{} {{", function.entry).unwrap();
+            writeln!(out, "        label=\"fn {:04}\";", function.entry).unwrap();
+            for &start in function.blocks.iter().filter(|&&b| placed.insert(b)) {
+                let block = &self.blocks[&start];
+                let mut label: String = block.instructions.iter()
+                    .map(|(addr, ins)| format!("{:04}: {}\\l", addr, ins))
+                    .collect();
+                match block.exit {
+                    Exit::Halt => label.push_str("(halt)\\l"),
+                    Exit::Invalid => label.push_str("(invalid)\\l"),
+                    Exit::Jump | Exit::Fallthrough => (),
+                }
+                writeln!(out, "        b{} [label=\"{}\"];", start, label).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+        if self.edges.iter().any(|e| e.to.is_none()) {
+            writeln!(out, "    unknown [shape=none, label=\"?\"];").unwrap();
+        }
+        for edge in self.edges.iter() {
+            let to = edge.to.map(|to| format!("b{}", to)).unwrap_or_else(|| "unknown".to_owned());
+            let style = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Jump => " [label=\"jump\"]",
+                EdgeKind::Branch => " [label=\"branch\"]",
+                EdgeKind::Call => " [label=\"call\", color=blue]",
+                EdgeKind::AfterCall => " [style=dotted]",
+                EdgeKind::Return => " [label=\"return\", style=dashed]",
+                EdgeKind::Computed => " [label=\"computed\", style=dashed]",
+            };
+            writeln!(out, "    b{} -> {}{};", edge.from, to, style).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::intcode::asm::assemble;
+
+    #[test]
+    fn calls_and_functions() {
+        let code = assemble("
+            arb #100
+            add #back, #0, [rb]
+            jt #1, #double
+        back:
+            out [rb+1]
+            hlt
+        double:
+            arb #2
+            mul [rb-1], #2, [rb-1]
+            arb #-2
+            jt #1, [rb]
+        ").unwrap();
+        let cfg = Cfg::build(&code);
+        assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0, 9, 12]);
+        assert_eq!(cfg.blocks[&9].exit, Exit::Halt);
+        assert_eq!(cfg.edges, vec![
+            Edge{from: 0, to: Some(12), kind: EdgeKind::Call},
+            Edge{from: 0, to: Some(9), kind: EdgeKind::AfterCall},
+            Edge{from: 12, to: None, kind: EdgeKind::Return},
+        ]);
+        let double = &cfg.functions[&12];
+        assert_eq!((double.frame, double.blocks.iter().copied().collect::<Vec<_>>()), (Some(2), vec![12]));
+        assert_eq!(cfg.functions[&0].blocks.iter().copied().collect::<Vec<_>>(), vec![0, 9]);
+    }
+
+    #[test]
+    fn branches_and_computed_jumps() {
+        // The halt is never reached, `jf #0` always jumps.
+        let cfg = Cfg::build(&[3, 10, 1005, 10, 0, 106, 0, 11, 99]);
+        assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0, 5]);
+        assert_eq!(cfg.successors(0).collect::<Vec<_>>(), vec![
+            &Edge{from: 0, to: Some(0), kind: EdgeKind::Branch},
+            &Edge{from: 0, to: Some(5), kind: EdgeKind::Fallthrough},
+        ]);
+        assert_eq!(cfg.successors(5).map(|e| e.kind).collect::<Vec<_>>(), vec![EdgeKind::Computed]);
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b0 [label=\"0000: in [10]\\l0002: jt [10], #0\\l\"];"));
+        assert!(dot.contains("    b5 -> unknown [label=\"computed\", style=dashed];\n"));
+    }
+}
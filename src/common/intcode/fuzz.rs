@@ -0,0 +1,287 @@
+//! Random Intcode programs and differential testing of the execution engines.
+//!
+//! `generate` makes small programs out of valid instructions: writes go to a data area after the
+//!  code or onto instruction words, so self-modification happens but addresses stay small.
+//! `compare` steps every engine in lockstep and reports the first instruction where results,
+//!  registers or memory differ, `minimize` shrinks a failing program while it keeps failing.
+//! `Dense` memory is left out, a single far write would allocate everything below it.
+use super::{
+    engine::Engine,
+    instruction::{Mode, Op, OPS},
+    memory::{Memory, Paged},
+    threaded::Threaded,
+    vm::Intcode,
+    DWord,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// Cells after the code that programs use for data.
+const DATA: usize = 8;
+/// Memory past the code that gets compared between engines.
+const EXTENT: usize = 64;
+
+/// Seeded xorshift64* generator, enough for reproducible programs without extra dependencies.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+
+    /// Uniform in `from..=to`.
+    pub fn range(&mut self, from: DWord, to: DWord) -> DWord {
+        from + self.below((to - from + 1) as usize) as DWord
+    }
+}
+
+/// A program of up to `size` random instructions followed by a halt and the data area.
+pub fn generate(rng: &mut Rng, size: usize) -> Vec<DWord> {
+    // Halt is the last of `OPS` and only goes at the end.
+    let ops: Vec<Op> = (0..1 + rng.below(size)).map(|_| OPS[rng.below(OPS.len() - 1)]).collect();
+    let starts: Vec<usize> = ops.iter()
+        .scan(0, |at, op| {
+            let start = *at;
+            *at += 1 + op.arity();
+            Some(start)
+        })
+        .collect();
+    let halt = starts.last().map(|&s| s + 1 + ops[ops.len() - 1].arity()).unwrap_or(0);
+    let total = halt + 1 + DATA;
+
+    let mut code = Vec::with_capacity(total);
+    for &op in ops.iter() {
+        let mut word = op.code();
+        let mut params = Vec::new();
+        for (i, scale) in [100, 1000, 10000].iter().take(op.arity()).enumerate() {
+            let jump = i == 1 && (op == Op::JumpIfTrue || op == Op::JumpIfFalse);
+            let (mode, value) = if op.write_param() == Some(i) {
+                let target = if rng.below(4) == 0 {
+                    starts[rng.below(starts.len())]
+                } else {
+                    halt + 1 + rng.below(DATA)
+                };
+                match rng.below(3) {
+                    0 => (Mode::Relative, target as DWord),
+                    _ => (Mode::Position, target as DWord),
+                }
+            } else if jump && rng.below(4) != 0 {
+                (Mode::Immediate, starts[rng.below(starts.len())] as DWord)
+            } else if op == Op::AdjustBase {
+                (Mode::Immediate, rng.range(-3, 3))
+            } else {
+                match rng.below(3) {
+                    0 => (Mode::Position, rng.below(total) as DWord),
+                    1 => (Mode::Immediate, rng.range(-10, 10)),
+                    _ => (Mode::Relative, rng.below(total) as DWord),
+                }
+            };
+            word += mode.digit() * scale;
+            params.push(value);
+        }
+        code.push(word);
+        code.extend(params);
+    }
+    code.push(Op::Halt.code());
+    code.extend((0..DATA).map(|_| rng.range(-5, 5)));
+    code
+}
+
+/// Makes an engine loaded with the code and inputs.
+pub type Factory = fn(&[DWord], &[DWord]) -> Box<dyn Engine>;
+
+fn interpreter<M: Memory + 'static>(code: &[DWord], inputs: &[DWord]) -> Box<dyn Engine> {
+    Box::new(Intcode::<M>::with_backend(code.to_vec(), inputs.to_vec()))
+}
+
+fn threaded<M: Memory + 'static>(code: &[DWord], inputs: &[DWord]) -> Box<dyn Engine> {
+    Box::new(Threaded::from(Intcode::<M>::with_backend(code.to_vec(), inputs.to_vec())))
+}
+
+/// Every engine worth comparing, by name.
+pub fn engines() -> Vec<(&'static str, Factory)> {
+    vec![
+        ("paged", interpreter::<Paged>),
+        ("btree", interpreter::<BTreeMap<usize, DWord>>),
+        ("threaded", threaded::<Paged>),
+        ("threaded-btree", threaded::<BTreeMap<usize, DWord>>),
+    ]
+}
+
+/// The first instruction where the engines disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub code: Vec<DWord>,
+    pub inputs: Vec<DWord>,
+    /// Number of instructions stepped before, including the diverging one.
+    pub step: usize,
+    /// Where the diverging instruction is.
+    pub pc: usize,
+    /// What each engine did, by name.
+    pub observed: Vec<(&'static str, String)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "engines diverge at step {} (pc={})", self.step, self.pc)?;
+        for (name, observed) in self.observed.iter() {
+            writeln!(f, "  {}: {}", name, observed)?;
+        }
+        let join = |words: &[DWord]| words.iter().map(DWord::to_string).collect::<Vec<_>>().join(",");
+        writeln!(f, "code: {}", join(&self.code))?;
+        write!(f, "inputs: {}", join(&self.inputs))
+    }
+}
+
+/// Steps all engines up to `limit` instructions, stops at the first difference or error.
+pub fn compare(engines: &[(&'static str, Factory)], code: &[DWord], inputs: &[DWord], limit: usize) -> Option<Divergence> {
+    let mut running: Vec<(&'static str, Box<dyn Engine>)> = engines.iter()
+        .map(|&(name, make)| (name, make(code, inputs)))
+        .collect();
+    let extent = code.len() + EXTENT;
+    for step in 1..=limit {
+        let pc = running[0].1.pc();
+        let mut failed = false;
+        let mut observed: Vec<(&'static str, String)> = running.iter_mut()
+            .map(|(name, engine)| {
+                let result = engine.step();
+                failed |= result.is_err();
+                let state = format!("{:?} pc={} state={:?} executed={}", result, engine.pc(), engine.state(), engine.executed());
+                (*name, state)
+            })
+            .collect();
+        if observed.iter().all(|(_, o)| *o == observed[0].1) {
+            let differs = (0..extent).find(|&addr| running.iter().any(|(_, e)| e.peek(addr) != running[0].1.peek(addr)));
+            match differs {
+                Some(addr) => {
+                    observed = running.iter().map(|(name, e)| (*name, format!("[{}]={}", addr, e.peek(addr)))).collect();
+                }
+                None if failed => return None,
+                None => continue,
+            }
+        }
+        return Some(Divergence{code: code.to_vec(), inputs: inputs.to_vec(), step, pc, observed});
+    }
+    None
+}
+
+/// Shrinks `code` by dropping chunks of words and simplifying values as long as `fails` holds.
+pub fn minimize<F: FnMut(&[DWord]) -> bool>(code: &[DWord], mut fails: F) -> Vec<DWord> {
+    let mut code = code.to_vec();
+    let mut chunk = (code.len() / 2).max(1);
+    loop {
+        let mut changed = false;
+        let mut i = 0;
+        while i < code.len() {
+            let mut candidate = code[..i].to_vec();
+            candidate.extend_from_slice(&code[(i + chunk).min(code.len())..]);
+            if !candidate.is_empty() && fails(&candidate) {
+                code = candidate;
+                changed = true;
+            } else {
+                i += chunk;
+            }
+        }
+        if chunk > 1 {
+            chunk /= 2;
+            continue;
+        }
+        for i in 0..code.len() {
+            for &simpler in [0, 1, code[i] / 2].iter() {
+                if simpler.abs() < code[i].abs() {
+                    let mut candidate = code.clone();
+                    candidate[i] = simpler;
+                    if fails(&candidate) {
+                        code = candidate;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if !changed {
+            return code;
+        }
+    }
+}
+
+/// Runs `runs` random programs on every engine, returns the first divergence found, minimized.
+pub fn fuzz(seed: u64, runs: usize, size: usize, limit: usize) -> Result<(), Divergence> {
+    let mut rng = Rng::new(seed);
+    let engines = engines();
+    for _ in 0..runs {
+        let code = generate(&mut rng, size);
+        let inputs: Vec<DWord> = (0..rng.below(4)).map(|_| rng.range(-5, 5)).collect();
+        if compare(&engines, &code, &inputs, limit).is_some() {
+            let code = minimize(&code, |code| compare(&engines, code, &inputs, limit).is_some());
+            return Err(compare(&engines, &code, &inputs, limit).expect("minimized program still diverges"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{error::IntcodeError, vm::State};
+    use std::collections::VecDeque;
+
+    #[test]
+    fn engines_agree() {
+        if let Err(divergence) = fuzz(2019, 500, 16, 500) {
+            panic!("{}", divergence);
+        }
+    }
+
+    #[test]
+    fn programs_are_valid() {
+        let mut rng = Rng::new(7);
+        for _ in 0..50 {
+            let code = generate(&mut rng, 8);
+            let mut vm = Intcode::new(code, vec![]);
+            vm.budget_mut().instructions(1);
+            match vm.step() {
+                Ok(_) | Err(IntcodeError::NeedsInput) => (),
+                Err(e) => panic!("first instruction failed: {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn finds_and_minimizes() {
+        // Pretends to be an engine that outputs one more than it should.
+        let sloppy: Factory = |code, inputs| {
+            struct Sloppy(Intcode);
+            impl Engine for Sloppy {
+                fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
+                    self.0.step().map(|output| output.map(|value| value + 1))
+                }
+                fn state(&self) -> State { self.0.state() }
+                fn pc(&self) -> usize { self.0.pc() }
+                fn executed(&self) -> u64 { self.0.executed() }
+                fn peek(&self, addr: usize) -> DWord { self.0[addr] }
+                fn inputs_mut(&mut self) -> &mut VecDeque<DWord> { &mut self.0.inputs }
+            }
+            Box::new(Sloppy(Intcode::new(code.to_vec(), inputs.to_vec())))
+        };
+        let engines = vec![engines()[0], ("sloppy", sloppy)];
+        let code = vec![1101, 2, 3, 9, 104, 5, 1, 9, 9, 9, 99];
+        let divergence = compare(&engines, &code, &[], 100).unwrap();
+        assert_eq!((divergence.step, divergence.pc), (2, 4));
+        let minimized = minimize(&code, |code| compare(&engines, code, &[], 100).is_some());
+        assert!(minimized.len() <= 5, "{:?}", minimized);
+        assert!(compare(&engines, &minimized, &[], 100).is_some());
+    }
+}
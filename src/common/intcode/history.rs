@@ -0,0 +1,259 @@
+//! Reverse execution for `Intcode`.
+//!
+//! `History` records how to undo every instruction it executes: the pc, base and state before
+//!  it, the old value of the cell it wrote and the input it consumed. The log is split into
+//!  segments that start with a checkpoint of the whole VM, so rewinding past a segment restores
+//!  its checkpoint at once, and only the last `keep` segments are kept to bound memory.
+use super::{
+    error::IntcodeError,
+    instruction::Op,
+    memory::{Memory, Paged},
+    vm::{Intcode, State},
+    DWord,
+};
+use std::collections::VecDeque;
+
+/// How to undo one executed instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Undo {
+    pc: usize,
+    base: DWord,
+    state: State,
+    /// Written address and its previous value.
+    write: Option<(usize, DWord)>,
+    input: Option<DWord>,
+    output: Option<DWord>,
+}
+
+/// The VM as it was before `undo[0]`, never empty.
+#[derive(Clone, Debug)]
+struct Segment<M: Memory> {
+    checkpoint: Intcode<M>,
+    undo: Vec<Undo>,
+}
+
+#[derive(Clone, Debug)]
+pub struct History<M: Memory = Paged> {
+    vm: Intcode<M>,
+    segments: VecDeque<Segment<M>>,
+    interval: usize,
+    keep: usize,
+}
+
+impl<M: Memory> From<Intcode<M>> for History<M> {
+    fn from(vm: Intcode<M>) -> Self {
+        History{vm, segments: VecDeque::new(), interval: 10_000, keep: 10}
+    }
+}
+
+impl<M: Memory> History<M> {
+    pub fn vm(&self) -> &Intcode<M> {
+        &self.vm
+    }
+
+    /// Gives access to the wrapped VM, changes made through it aren't undone.
+    pub fn vm_mut(&mut self) -> &mut Intcode<M> {
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> Intcode<M> {
+        self.vm
+    }
+
+    /// Takes a checkpoint every `interval` instructions, 10000 by default.
+    pub fn checkpoint_every(&mut self, interval: usize) -> &mut Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// Keeps only the last `checkpoints` checkpoints and what was executed since, 10 by default.
+    pub fn keep(&mut self, checkpoints: usize) -> &mut Self {
+        self.keep = checkpoints.max(1);
+        while self.segments.len() > self.keep {
+            self.segments.pop_front();
+        }
+        self
+    }
+
+    /// Number of instructions that can be stepped back.
+    pub fn recorded(&self) -> usize {
+        self.segments.iter().map(|s| s.undo.len()).sum()
+    }
+
+    /// Same as `Intcode::step`, but records how to undo the instruction.
+    pub fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
+        let (pc, base, state) = (self.vm.pc(), self.vm.base(), self.vm.state());
+        let (target, op) = match self.vm.parse_opcode() {
+            Ok((args, op)) => {
+                let op = Op::from_code(op as DWord);
+                (op.and_then(Op::write_param).map(|i| args[i]), op)
+            }
+            Err(_) => (None, None),
+        };
+        let write = target.map(|addr| (addr, self.vm[addr]));
+        let full = self.segments.back().map(|s| s.undo.len() >= self.interval).unwrap_or(true);
+        let checkpoint = if full { Some(self.vm.clone()) } else { None };
+
+        let executed = self.vm.executed();
+        let result = self.vm.step();
+        if self.vm.executed() == executed {
+            return result;
+        }
+        let input = match (op, write) {
+            (Some(Op::In), Some((addr, _))) => Some(self.vm[addr]),
+            _ => None,
+        };
+        let output = result.as_ref().ok().and_then(|&output| output);
+        if let Some(checkpoint) = checkpoint {
+            self.segments.push_back(Segment{checkpoint, undo: Vec::new()});
+            if self.segments.len() > self.keep {
+                self.segments.pop_front();
+            }
+        }
+        let segment = self.segments.back_mut().expect("segment was just made");
+        segment.undo.push(Undo{pc, base, state, write, input, output});
+        result
+    }
+
+    /// Undoes the last executed instruction, returns None if there's nothing recorded.
+    fn undo(&mut self) -> Option<Undo> {
+        let segment = self.segments.back_mut()?;
+        let undo = segment.undo.pop().expect("segments are never empty");
+        if segment.undo.is_empty() {
+            self.segments.pop_back();
+        }
+        let vm = &mut self.vm;
+        vm.pc = undo.pc;
+        vm.base = undo.base;
+        vm.state = undo.state;
+        vm.executed -= 1;
+        if let Some((addr, old)) = undo.write {
+            vm[addr] = old;
+        }
+        if let Some(value) = undo.input {
+            vm.inputs.push_front(value);
+        }
+        Some(undo)
+    }
+
+    /// Steps one instruction backwards, returns false if there's nothing recorded.
+    pub fn back(&mut self) -> bool {
+        self.undo().is_some()
+    }
+
+    /// Steps `n` instructions backwards, or as many as are recorded, and returns how many.
+    ///
+    /// Whole segments are skipped by restoring their checkpoint. Only the memory, pc, base and
+    ///  state come from it, inputs get their consumed values back and the budget keeps counting.
+    pub fn rewind(&mut self, n: usize) -> usize {
+        let mut done = 0;
+        while done < n {
+            let len = match self.segments.back() {
+                Some(segment) => segment.undo.len(),
+                None => break,
+            };
+            if n - done < len {
+                self.undo();
+                done += 1;
+                continue;
+            }
+            let segment = self.segments.pop_back().expect("checked above");
+            let consumed = segment.undo.iter().filter_map(|u| u.input);
+            let inputs = consumed.chain(self.vm.inputs.drain(..)).collect();
+            let vm = segment.checkpoint;
+            self.vm.pc = vm.pc;
+            self.vm.base = vm.base;
+            self.vm.state = vm.state;
+            self.vm.executed = vm.executed;
+            self.vm.memory = vm.memory;
+            self.vm.inputs = inputs;
+            done += len;
+        }
+        done
+    }
+
+    /// Steps backwards to right before the last write to `addr`, so the writer is at pc.
+    /// Returns false and stops at the oldest recorded instruction if there's none.
+    pub fn back_to_write(&mut self, addr: usize) -> bool {
+        self.back_until(|undo| undo.write.map(|(at, _)| at) == Some(addr))
+    }
+
+    /// Steps backwards to right before the last output and returns its value.
+    /// Returns None and stops at the oldest recorded instruction if there's none.
+    pub fn back_to_output(&mut self) -> Option<DWord> {
+        let mut output = None;
+        self.back_until(|undo| {
+            output = undo.output;
+            output.is_some()
+        });
+        output
+    }
+
+    fn back_until<F: FnMut(&Undo) -> bool>(&mut self, mut found: F) -> bool {
+        while let Some(undo) = self.undo() {
+            if found(&undo) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl<M: Memory> Iterator for History<M> {
+    type Item = Result<DWord, IntcodeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step() {
+                Ok(Some(o)) => return Some(Ok(o)),
+                Err(IntcodeError::Halted) => return None,
+                Err(e) => return Some(Err(e)),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    #[test]
+    fn back_to_start() -> Result<(), IntcodeError> {
+        let start = Intcode::new(vec![3,9,8,9,10,9,4,9,99,-1,8], vec![8]);
+        let mut history = History::from(start.clone());
+        assert_eq!(history.by_ref().collect::<Result<Vec<_>, _>>()?, vec![1]);
+        assert_eq!(history.recorded(), 4);
+        assert_eq!(history.back_to_output(), Some(1));
+        assert_eq!(history.vm().pc(), 6);
+        assert!(history.back_to_write(9));
+        assert_eq!((history.vm().pc(), history.vm()[9]), (2, 8));
+        assert!(!history.back_to_write(100));
+        assert_eq!(history.vm(), &start);
+        Ok(())
+    }
+
+    #[test]
+    fn bounded_checkpoints() -> Result<(), IntcodeError> {
+        let mut history = History::from(QUINE.parse::<Intcode>()?);
+        history.checkpoint_every(4).keep(3);
+        let mut replay = QUINE.parse::<Intcode>()?;
+        for _ in 0..30 {
+            history.step()?;
+        }
+        let mut undone = history.clone();
+        assert_eq!(history.recorded(), 10);
+        assert_eq!(history.rewind(7), 7);
+        while undone.recorded() > 3 {
+            undone.back();
+        }
+        assert_eq!(history.vm(), undone.vm());
+        assert_eq!(history.rewind(100), 3);
+        for _ in 0..20 {
+            replay.step()?;
+        }
+        assert_eq!(history.vm(), &replay);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Decoding of raw Intcode words into instructions, shared by the tooling around the VM.
+use super::DWord;
+use std::fmt;
+
+/// Parameter mode, the digits above the opcode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_digit(digit: DWord) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn digit(self) -> DWord {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+    Add,
+    Mul,
+    In,
+    Out,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Halt,
+}
+
+pub const OPS: [Op; 10] = [
+    Op::Add, Op::Mul, Op::In, Op::Out, Op::JumpIfTrue,
+    Op::JumpIfFalse, Op::LessThan, Op::Equals, Op::AdjustBase, Op::Halt,
+];
+
+impl Op {
+    pub fn from_code(code: DWord) -> Option<Op> {
+        OPS.iter().copied().find(|op| op.code() == code)
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Op> {
+        OPS.iter().copied().find(|op| op.mnemonic() == mnemonic)
+    }
+
+    pub fn code(self) -> DWord {
+        match self {
+            Op::Add => 1,
+            Op::Mul => 2,
+            Op::In => 3,
+            Op::Out => 4,
+            Op::JumpIfTrue => 5,
+            Op::JumpIfFalse => 6,
+            Op::LessThan => 7,
+            Op::Equals => 8,
+            Op::AdjustBase => 9,
+            Op::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Mul => "mul",
+            Op::In => "in",
+            Op::Out => "out",
+            Op::JumpIfTrue => "jt",
+            Op::JumpIfFalse => "jf",
+            Op::LessThan => "lt",
+            Op::Equals => "eq",
+            Op::AdjustBase => "arb",
+            Op::Halt => "hlt",
+        }
+    }
+
+    /// Number of parameters following the opcode word.
+    pub fn arity(self) -> usize {
+        match self {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => 3,
+            Op::JumpIfTrue | Op::JumpIfFalse => 2,
+            Op::In | Op::Out | Op::AdjustBase => 1,
+            Op::Halt => 0,
+        }
+    }
+
+    /// Index of the parameter this op writes to, if any.
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => Some(2),
+            Op::In => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Single decoded parameter: its mode and the raw word stored after the opcode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: DWord,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value == 0 => write!(f, "[rb]"),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub op: Op,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    /// Decodes instruction at `at`, returns None if the words there aren't a valid instruction.
+    ///
+    /// Besides unknown opcodes and mode digits this also rejects writes in immediate mode,
+    ///  mode digits for parameters the op doesn't have and instructions running past the end.
+    pub fn decode(memory: &[DWord], at: usize) -> Option<Instruction> {
+        let word = *memory.get(at)?;
+        if word < 0 {
+            return None;
+        }
+        let op = Op::from_code(word % 100)?;
+        let mut modes = word / 100;
+        let mut params = Vec::with_capacity(op.arity());
+        for i in 0..op.arity() {
+            let mode = Mode::from_digit(modes % 10)?;
+            if mode == Mode::Immediate && op.write_param() == Some(i) {
+                return None;
+            }
+            params.push(Param{mode, value: *memory.get(at + 1 + i)?});
+            modes /= 10;
+        }
+        if modes != 0 {
+            return None;
+        }
+        Some(Instruction{op, params})
+    }
+
+    /// Number of words this instruction occupies.
+    pub fn size(&self) -> usize {
+        1 + self.params.len()
+    }
+
+    /// Raw opcode word including parameter modes.
+    pub fn opcode(&self) -> DWord {
+        self.params.iter().rev()
+            .fold(0, |acc, p| acc * 10 + p.mode.digit()) * 100 + self.op.code()
+    }
+
+    pub fn encode(&self) -> Vec<DWord> {
+        let mut words = Vec::with_capacity(self.size());
+        words.push(self.opcode());
+        words.extend(self.params.iter().map(|p| p.value));
+        words
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.op.mnemonic())?;
+        for (i, param) in self.params.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, param)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_modes() {
+        let ins = Instruction::decode(&[21101, 5, -3, 7], 0).unwrap();
+        assert_eq!(ins.op, Op::Add);
+        assert_eq!(ins.to_string(), "add #5, #-3, [rb+7]");
+        assert_eq!(ins.encode(), vec![21101, 5, -3, 7]);
+    }
+
+    #[test]
+    fn decode_rejects_garbage() {
+        // unknown opcode, immediate write, stray mode digit, truncated
+        assert_eq!(Instruction::decode(&[42], 0), None);
+        assert_eq!(Instruction::decode(&[10001, 0, 0, 0], 0), None);
+        assert_eq!(Instruction::decode(&[10004, 0], 0), None);
+        assert_eq!(Instruction::decode(&[1, 0, 0], 0), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Loading Intcode programs from text, files and a compact binary encoding.
+//!
+//! Text is comma separated with any whitespace, newlines included, around values, and a trailing
+//!  comma is fine. Whitespace inside a value is an error rather than a separator. Errors point at the offending value by index and byte offset.
+//! The binary encoding is `MAGIC` followed by every value as a zigzag LEB128 varint, which
+//!  keeps the usual small values at a byte or two.
+use super::{
+    error::LoadError,
+    vm::Intcode,
+    DWord,
+};
+use std::{convert::TryFrom, fs, path::Path, str::FromStr};
+
+/// Starts every binary program, the NUL keeps it from ever being valid text.
+pub const MAGIC: &[u8; 4] = b"\0ICB";
+
+/// Parses comma separated values.
+pub fn parse(s: &str) -> Result<Vec<DWord>, LoadError> {
+    if s.trim().is_empty() {
+        return Err(LoadError::Empty);
+    }
+    let mut code = Vec::new();
+    let pieces: Vec<&str> = s.split(',').collect();
+    let mut offset = 0;
+    for (i, piece) in pieces.iter().enumerate() {
+        let token = piece.trim();
+        let at = offset + piece.len() - piece.trim_start().len();
+        let trailing = i + 1 == pieces.len() && i > 0;
+        if token.is_empty() {
+            if !trailing {
+                return Err(LoadError::MissingValue{index: code.len(), offset: at});
+            }
+        } else {
+            let value = token.parse().map_err(|cause| LoadError::BadToken{index: code.len(), offset: at, token: token.to_owned(), cause})?;
+            code.push(value);
+        }
+        offset += piece.len() + 1;
+    }
+    Ok(code)
+}
+
+/// Encodes a program in the binary format.
+pub fn to_binary(code: &[DWord]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    for &value in code {
+        let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+        loop {
+            let byte = (zigzag & 0x7f) as u8;
+            zigzag >>= 7;
+            if zigzag == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+    bytes
+}
+
+/// Decodes a program in the binary format.
+pub fn from_binary(bytes: &[u8]) -> Result<Vec<DWord>, LoadError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(LoadError::NotBinary);
+    }
+    let mut code = Vec::new();
+    let mut at = MAGIC.len();
+    while at < bytes.len() {
+        let start = at;
+        let mut zigzag = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.get(at).ok_or(LoadError::Truncated{offset: start})?;
+            at += 1;
+            if shift > 63 || (shift == 63 && byte & 0x7e != 0) {
+                return Err(LoadError::TooLarge{offset: start});
+            }
+            zigzag |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        code.push((zigzag >> 1) as DWord ^ -((zigzag & 1) as DWord));
+    }
+    if code.is_empty() {
+        return Err(LoadError::Empty);
+    }
+    Ok(code)
+}
+
+/// Reads a program from a file in either format.
+pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<DWord>, LoadError> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        return from_binary(&bytes);
+    }
+    parse(std::str::from_utf8(&bytes).map_err(|_| LoadError::NotText)?)
+}
+
+impl FromStr for Intcode {
+    type Err = LoadError;
+
+    fn from_str(s: &str) -> Result<Self, LoadError> {
+        Ok(Intcode::new(parse(s)?, Vec::new()))
+    }
+}
+
+impl TryFrom<&str> for Intcode {
+    type Error = LoadError;
+
+    fn try_from(s: &str) -> Result<Self, LoadError> {
+        s.parse()
+    }
+}
+
+impl Intcode {
+    /// Loads a program from a text or binary file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        Ok(Intcode::new(from_file(path)?, Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace() -> Result<(), LoadError> {
+        assert_eq!(parse("1,2,3\n")?, vec![1, 2, 3]);
+        assert_eq!(parse("  1 ,\n-2,\r\n3,\n")?, vec![1, -2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        match parse("1,2,x3,4") {
+            Err(LoadError::BadToken{index: 2, offset: 4, token, ..}) => assert_eq!(token, "x3"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("1,2 3,4") {
+            Err(LoadError::BadToken{index: 1, offset: 2, token, ..}) => assert_eq!(token, "2 3"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("1,\n ,3") {
+            Err(LoadError::MissingValue{index: 1, offset: 4}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(" \n") {
+            Err(LoadError::Empty) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse("1,2,x3").unwrap_err().to_string(),
+            "value 2 at byte 4 is not a number: `x3`",
+        );
+    }
+
+    #[test]
+    fn binary() -> Result<(), LoadError> {
+        let code = vec![109, 1, 204, -1, 0, 99, DWord::MAX, DWord::MIN];
+        let bytes = to_binary(&code);
+        assert_eq!(&bytes[..8], b"\0ICB\xda\x01\x02\x98");
+        assert_eq!(from_binary(&bytes)?, code);
+        match from_binary(&bytes[..bytes.len() - 1]) {
+            Err(LoadError::Truncated{..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        Ok(())
+    }
+}
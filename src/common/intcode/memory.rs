@@ -0,0 +1,219 @@
+//! Storage backends for `Intcode` memory.
+//!
+//! Unset cells read as 0, writing anywhere allocates as needed.
+//! - `Dense` is a plain growable `Vec`, fastest but a far write allocates everything below it.
+//! - `Paged` allocates fixed-size pages on demand, so far writes only cost a page.
+//!   Pages are shared between clones and copied on first write, so cloning a VM is cheap.
+//! - `BTreeMap` is the original sparse map, kept around for comparison.
+use super::DWord;
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+pub trait Memory: Clone + Debug + Default + PartialEq + Eq {
+    fn get(&self, addr: usize) -> Option<&DWord>;
+
+    fn get_mut(&mut self, addr: usize) -> &mut DWord;
+
+    fn insert(&mut self, addr: usize, value: DWord) {
+        *self.get_mut(addr) = value;
+    }
+
+    /// Number of cells backed by storage, touched or not.
+    fn allocated(&self) -> usize;
+
+    /// Every allocated cell in address order, zeroes included.
+    fn cells(&self) -> Box<dyn Iterator<Item = (usize, DWord)> + '_>;
+
+    /// Cells holding anything but 0, which is what two memories are compared by.
+    fn nonzero_cells(&self) -> Box<dyn Iterator<Item = (usize, DWord)> + '_> {
+        Box::new(self.cells().filter(|&(_, value)| value != 0))
+    }
+
+    fn from_code(code: Vec<DWord>) -> Self {
+        let mut memory = Self::default();
+        for (addr, value) in code.into_iter().enumerate() {
+            memory.insert(addr, value);
+        }
+        memory
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dense(Vec<DWord>);
+
+impl PartialEq for Dense {
+    fn eq(&self, other: &Self) -> bool {
+        self.nonzero_cells().eq(other.nonzero_cells())
+    }
+}
+
+impl Eq for Dense {}
+
+impl Memory for Dense {
+    fn get(&self, addr: usize) -> Option<&DWord> {
+        self.0.get(addr)
+    }
+
+    fn get_mut(&mut self, addr: usize) -> &mut DWord {
+        if addr >= self.0.len() {
+            self.0.resize(addr + 1, 0);
+        }
+        &mut self.0[addr]
+    }
+
+    fn allocated(&self) -> usize {
+        self.0.len()
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (usize, DWord)> + '_> {
+        Box::new(self.0.iter().copied().enumerate())
+    }
+
+    fn from_code(code: Vec<DWord>) -> Self {
+        Dense(code)
+    }
+}
+
+pub const PAGE_SIZE: usize = 1024;
+/// Pages below this number live in a directory vec, the rest in a map.
+const DIRECTORY_PAGES: usize = 1 << 16;
+
+type Page = Arc<[DWord; PAGE_SIZE]>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Paged {
+    directory: Vec<Option<Page>>,
+    far: BTreeMap<usize, Page>,
+    count: usize,
+}
+
+impl PartialEq for Paged {
+    fn eq(&self, other: &Self) -> bool {
+        self.nonzero_cells().eq(other.nonzero_cells())
+    }
+}
+
+impl Eq for Paged {}
+
+impl Paged {
+    fn page(&self, number: usize) -> Option<&Page> {
+        if number < DIRECTORY_PAGES {
+            self.directory.get(number)?.as_ref()
+        } else {
+            self.far.get(&number)
+        }
+    }
+
+    /// Number of pages allocated so far.
+    pub fn pages(&self) -> usize {
+        self.count
+    }
+}
+
+impl Memory for Paged {
+    fn get(&self, addr: usize) -> Option<&DWord> {
+        self.page(addr / PAGE_SIZE).map(|page| &page[addr % PAGE_SIZE])
+    }
+
+    fn get_mut(&mut self, addr: usize) -> &mut DWord {
+        let number = addr / PAGE_SIZE;
+        let count = &mut self.count;
+        let new_page = || {
+            *count += 1;
+            Arc::new([0; PAGE_SIZE])
+        };
+        let page = if number < DIRECTORY_PAGES {
+            if number >= self.directory.len() {
+                self.directory.resize(number + 1, None);
+            }
+            self.directory[number].get_or_insert_with(new_page)
+        } else {
+            self.far.entry(number).or_insert_with(new_page)
+        };
+        // Copies the page if some clone still shares it.
+        &mut Arc::make_mut(page)[addr % PAGE_SIZE]
+    }
+
+    fn allocated(&self) -> usize {
+        self.count * PAGE_SIZE
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (usize, DWord)> + '_> {
+        let near = self.directory.iter()
+            .enumerate()
+            .filter_map(|(number, page)| page.as_ref().map(|page| (number, page)));
+        let far = self.far.iter().map(|(&number, page)| (number, page));
+        Box::new(near.chain(far).flat_map(|(number, page)| {
+            page.iter().enumerate().map(move |(i, &value)| (number * PAGE_SIZE + i, value))
+        }))
+    }
+}
+
+impl Memory for BTreeMap<usize, DWord> {
+    fn get(&self, addr: usize) -> Option<&DWord> {
+        BTreeMap::get(self, &addr)
+    }
+
+    fn get_mut(&mut self, addr: usize) -> &mut DWord {
+        self.entry(addr).or_default()
+    }
+
+    fn allocated(&self) -> usize {
+        self.len()
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (usize, DWord)> + '_> {
+        Box::new(self.iter().map(|(&addr, &value)| (addr, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn far_writes<M: Memory>() -> M {
+        let mut memory = M::from_code(vec![1, 2, 3]);
+        memory.insert(5000, 7);
+        *memory.get_mut(1) += 40;
+        assert_eq!(memory.get(1), Some(&42));
+        assert_eq!(memory.get(5000), Some(&7));
+        assert_eq!(memory.get(4999).copied().unwrap_or(0), 0);
+        assert_eq!(memory.get(1 << 40), None);
+        memory
+    }
+
+    #[test]
+    fn backends_agree() {
+        far_writes::<Dense>();
+        far_writes::<BTreeMap<usize, DWord>>();
+        let paged = far_writes::<Paged>();
+        assert_eq!(paged.pages(), 2);
+    }
+
+    #[test]
+    fn paged_clone_on_write() {
+        let mut original = Paged::from_code((0..3000).collect());
+        let mut clone = original.clone();
+        clone.insert(5, -5);
+        original.insert(2500, -1);
+
+        assert_eq!(original.get(5), Some(&5));
+        assert_eq!(clone.get(5), Some(&-5));
+        assert_eq!(clone.get(2500), Some(&2500));
+        // Only the pages written to after cloning got copied.
+        let shared = |n: usize| Arc::ptr_eq(
+            original.directory[n].as_ref().unwrap(),
+            clone.directory[n].as_ref().unwrap(),
+        );
+        assert!(!shared(0));
+        assert!(shared(1));
+        assert!(!shared(2));
+    }
+
+    #[test]
+    fn paged_far_address() {
+        let mut memory = Paged::default();
+        memory.insert(1 << 40, -1);
+        assert_eq!(memory.pages(), 1);
+        assert_eq!(memory.cells().find(|&(_, v)| v != 0), Some((1 << 40, -1)));
+    }
+}
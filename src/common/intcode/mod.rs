@@ -1,4 +1,30 @@
 pub mod error;
 pub mod vm;
+pub mod engine;
+pub mod device;
+pub mod aio;
+pub mod threaded;
+pub mod fuzz;
+pub mod conformance;
+pub mod budget;
+pub mod memory;
+pub mod load;
+pub mod patch;
+pub mod channel;
+pub mod instruction;
+pub mod disasm;
+pub mod flow;
+pub mod asm;
+pub mod debugger;
+pub mod history;
+pub mod trace;
+pub mod profile;
+pub mod selfmod;
+pub mod symbolic;
+pub mod snapshot;
+pub mod scheduler;
+pub mod ascii;
+#[cfg(feature = "bigint")]
+pub mod big;
 
-type DWord = i64;
+type DWord = i64;
\ No newline at end of file
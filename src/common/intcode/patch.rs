@@ -0,0 +1,369 @@
+//! Declarative patches for Intcode programs, checked against the original values and revertible.
+//!
+//! Patches are written in a small text format, one edit per line, grouped into named sets:
+//!
+//! ```text
+//! ; day 13
+//! [free-play]
+//! 0: 1 -> 2           ; address 0 must hold 1, writes 2
+//! [cheats]
+//! 1 = 12, 2           ; writes 12 and 2 at addresses 1 and 2 whatever they hold
+//! jump 4 -> 20        ; replaces the instruction at 4 with a jump to 20
+//! nop 8..12           ; turns cells 8 to 11 into instructions that do nothing
+//! ```
+//!
+//! Edits before the first `[name]` line go into a set named `default`.
+//! A jump takes three cells, so it can only replace instructions at least that long.
+//! Whatever follows it in a longer instruction is left as it was.
+//! Intcode has no no-op, so `nop` fills ranges with `arb #0` and `jt #0, #0`, which need at least two cells.
+use super::{
+    error::PatchError,
+    instruction::Instruction,
+    memory::Memory,
+    vm::Intcode,
+    DWord,
+};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    ops::Range,
+    path::Path,
+    str::FromStr,
+};
+
+/// Something a patch can be applied to.
+pub trait Image {
+    fn peek(&self, addr: usize) -> DWord;
+    fn poke(&mut self, addr: usize, value: DWord);
+}
+
+impl<M: Memory> Image for Intcode<M> {
+    fn peek(&self, addr: usize) -> DWord {
+        self[addr]
+    }
+
+    fn poke(&mut self, addr: usize, value: DWord) {
+        self[addr] = value;
+    }
+}
+
+impl Image for Vec<DWord> {
+    fn peek(&self, addr: usize) -> DWord {
+        self.get(addr).copied().unwrap_or(0)
+    }
+
+    fn poke(&mut self, addr: usize, value: DWord) {
+        if addr >= self.len() {
+            self.resize(addr + 1, 0);
+        }
+        self[addr] = value;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit {
+    /// Writes `values` starting at `addr`, which must hold `expect` first if it's given.
+    Set{addr: usize, values: Vec<DWord>, expect: Option<Vec<DWord>>},
+    /// Replaces the instruction at `at` with `jt #1, #to`.
+    Jump{at: usize, to: usize},
+    /// Fills `from..to` with instructions that do nothing.
+    Nop{from: usize, to: usize},
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |values: &[DWord]| values.iter().map(DWord::to_string).collect::<Vec<_>>().join(", ");
+        match self {
+            Edit::Set{addr, values, expect: None} => write!(f, "{} = {}", addr, list(values)),
+            Edit::Set{addr, values, expect: Some(old)} => write!(f, "{}: {} -> {}", addr, list(old), list(values)),
+            Edit::Jump{at, to} => write!(f, "jump {} -> {}", at, to),
+            Edit::Nop{from, to} => write!(f, "nop {}..{}", from, to),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub name: String,
+    pub edits: Vec<Edit>,
+}
+
+impl Patch {
+    pub fn new(name: &str) -> Self {
+        Patch{name: name.to_owned(), edits: Vec::new()}
+    }
+
+    /// Writes `values` starting at `addr`.
+    pub fn set(&mut self, addr: usize, values: &[DWord]) -> &mut Self {
+        self.edits.push(Edit::Set{addr, values: values.to_vec(), expect: None});
+        self
+    }
+
+    /// Writes `new` starting at `addr`, only if the cells hold `old`.
+    pub fn replace(&mut self, addr: usize, old: &[DWord], new: &[DWord]) -> &mut Self {
+        assert_eq!(old.len(), new.len(), "replacement must be as long as what it replaces");
+        self.edits.push(Edit::Set{addr, values: new.to_vec(), expect: Some(old.to_vec())});
+        self
+    }
+
+    pub fn jump(&mut self, at: usize, to: usize) -> &mut Self {
+        self.edits.push(Edit::Jump{at, to});
+        self
+    }
+
+    pub fn nop(&mut self, range: Range<usize>) -> &mut Self {
+        self.edits.push(Edit::Nop{from: range.start, to: range.end});
+        self
+    }
+
+    /// Every cell the patch writes, in order, after checking it fits `image`.
+    pub fn cells<I: Image>(&self, image: &I) -> Result<Vec<(usize, DWord)>, PatchError> {
+        // Later edits see what earlier ones wrote.
+        let mut written = BTreeMap::new();
+        let mut cells = Vec::new();
+        for edit in self.edits.iter() {
+            let peek = |addr: usize| written.get(&addr).copied().unwrap_or_else(|| image.peek(addr));
+            let values = match edit {
+                Edit::Set{addr, values, expect} => {
+                    for (i, &expected) in expect.iter().flatten().enumerate() {
+                        let found = peek(addr + i);
+                        if found != expected {
+                            return Err(PatchError::Mismatch{addr: addr + i, expected, found});
+                        }
+                    }
+                    (*addr..).zip(values.iter().copied()).collect()
+                }
+                Edit::Jump{at, to} => {
+                    let window: Vec<DWord> = (*at..at + 4).map(peek).collect();
+                    let size = Instruction::decode(&window, 0).ok_or(PatchError::NotAnInstruction{addr: *at})?.size();
+                    if size < 3 {
+                        return Err(PatchError::TooShortForJump{addr: *at, size});
+                    }
+                    (*at..).zip(vec![1105, 1, *to as DWord]).collect()
+                }
+                &Edit::Nop{from, to} => {
+                    if to < from + 2 {
+                        return Err(PatchError::BadRange{from, to});
+                    }
+                    let mut fill = Vec::new();
+                    if (to - from) % 2 == 1 {
+                        fill.extend(&[1105, 0, 0]);
+                    }
+                    while fill.len() < to - from {
+                        fill.extend(&[109, 0]);
+                    }
+                    (from..).zip(fill).collect::<Vec<_>>()
+                }
+            };
+            written.extend(values.iter().copied());
+            cells.extend(values);
+        }
+        Ok(cells)
+    }
+
+    /// Checks the patch could be applied to `image` without changing it.
+    pub fn check<I: Image>(&self, image: &I) -> Result<(), PatchError> {
+        self.cells(image).map(drop)
+    }
+
+    /// Applies the whole patch, or nothing of it if it doesn't fit.
+    pub fn apply<I: Image>(&self, image: &mut I) -> Result<Applied, PatchError> {
+        let cells = self.cells(image)?;
+        let mut writes = Vec::with_capacity(cells.len());
+        for (addr, new) in cells {
+            writes.push((addr, image.peek(addr), new));
+            image.poke(addr, new);
+        }
+        Ok(Applied{writes})
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[{}]", self.name)?;
+        for edit in self.edits.iter() {
+            writeln!(f, "{}", edit)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an applied patch overwrote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Applied {
+    /// Address, old and new value of every write, in order.
+    writes: Vec<(usize, DWord, DWord)>,
+}
+
+impl Applied {
+    /// Restores the old values, unless something else has written to the patched cells since.
+    pub fn revert<I: Image>(self, image: &mut I) -> Result<(), PatchError> {
+        let mut current = BTreeMap::new();
+        for &(addr, _, new) in self.writes.iter() {
+            current.insert(addr, new);
+        }
+        for (addr, expected) in current {
+            let found = image.peek(addr);
+            if found != expected {
+                return Err(PatchError::Mismatch{addr, expected, found});
+            }
+        }
+        for &(addr, old, _) in self.writes.iter().rev() {
+            image.poke(addr, old);
+        }
+        Ok(())
+    }
+}
+
+/// Named patch sets, as loaded from a patch file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patches(pub Vec<Patch>);
+
+impl Patches {
+    pub fn get(&self, name: &str) -> Result<&Patch, PatchError> {
+        self.0.iter().find(|patch| patch.name == name).ok_or_else(|| PatchError::UnknownName{name: name.to_owned()})
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PatchError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+fn numbers<T: FromStr>(text: &str) -> Option<Vec<T>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse().ok())
+        .collect()
+}
+
+fn single<T: FromStr>(text: &str) -> Option<T> {
+    text.trim().parse().ok()
+}
+
+fn parse_edit(text: &str) -> Option<Edit> {
+    let mut words = text.splitn(2, char::is_whitespace);
+    match (words.next(), words.next()) {
+        (Some("jump"), Some(rest)) => {
+            let mut parts = rest.splitn(2, "->");
+            return Some(Edit::Jump{at: single(parts.next()?)?, to: single(parts.next()?)?});
+        }
+        (Some("nop"), Some(rest)) => {
+            let mut parts = rest.splitn(2, "..");
+            return Some(Edit::Nop{from: single(parts.next()?)?, to: single(parts.next()?)?});
+        }
+        _ => (),
+    }
+    if let Some(at) = text.find('=') {
+        let values = numbers(&text[at + 1..]).filter(|values: &Vec<DWord>| !values.is_empty())?;
+        return Some(Edit::Set{addr: single(&text[..at])?, values, expect: None});
+    }
+    let at = text.find(':')?;
+    let mut parts = text[at + 1..].splitn(2, "->");
+    let expect: Vec<DWord> = numbers(parts.next()?)?;
+    let values: Vec<DWord> = numbers(parts.next()?)?;
+    if values.is_empty() || values.len() != expect.len() {
+        return None;
+    }
+    Some(Edit::Set{addr: single(&text[..at])?, values, expect: Some(expect)})
+}
+
+impl FromStr for Patches {
+    type Err = PatchError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut patches: Vec<Patch> = Vec::new();
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let text = raw.split(';').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            if text.starts_with('[') && text.ends_with(']') {
+                let name = text[1..text.len() - 1].trim();
+                if patches.iter().any(|patch| patch.name == name) {
+                    return Err(PatchError::DuplicateName{line, name: name.to_owned()});
+                }
+                patches.push(Patch::new(name));
+                continue;
+            }
+            let edit = parse_edit(text).ok_or_else(|| PatchError::Syntax{line, text: text.to_owned()})?;
+            if patches.is_empty() {
+                patches.push(Patch::new("default"));
+            }
+            patches.last_mut().expect("pushed above").edits.push(edit);
+        }
+        Ok(Patches(patches))
+    }
+}
+
+impl fmt::Display for Patches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for patch in self.0.iter() {
+            write!(f, "{}", patch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::error::IntcodeError;
+
+    const PATCHES: &str = "\
+; free play and a shortcut
+[free-play]
+0: 1 -> 2
+[skip]
+jump 0 -> 9     ; straight to the output
+nop 4..9
+[poke]
+20 = 7, 8
+";
+
+    #[test]
+    fn parse_and_print() -> Result<(), PatchError> {
+        let patches: Patches = PATCHES.parse()?;
+        assert_eq!(patches.0.len(), 3);
+        assert_eq!(patches.get("poke")?.edits, vec![Edit::Set{addr: 20, values: vec![7, 8], expect: None}]);
+        assert_eq!(patches.to_string().parse::<Patches>()?, patches);
+        assert_eq!("5 = 1\n".parse::<Patches>()?.0[0].name, "default");
+        match "[a]\n1: 2 -> 3, 4\n".parse::<Patches>() {
+            Err(PatchError::Syntax{line: 2, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match "[a]\n[a]\n".parse::<Patches>() {
+            Err(PatchError::DuplicateName{line: 2, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn apply_and_revert() -> Result<(), IntcodeError> {
+        let patches: Patches = PATCHES.parse().unwrap();
+        let code = vec![1, 0, 0, 0, 1101, 1, 1, 0, 99, 4, 0, 99];
+        let mut vm = Intcode::new(code.clone(), vec![]);
+        let applied = patches.get("skip").unwrap().apply(&mut vm).unwrap();
+        assert_eq!(vm.clone().collect::<Result<Vec<_>, _>>()?, vec![1105]);
+        applied.revert(&mut vm).unwrap();
+        assert_eq!(vm, Intcode::new(code.clone(), vec![]));
+
+        let free = patches.get("free-play").unwrap();
+        let mut image = code.clone();
+        free.apply(&mut image).unwrap();
+        assert_eq!(image[0], 2);
+        match free.apply(&mut image) {
+            Err(PatchError::Mismatch{addr: 0, expected: 1, found: 2}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match Patch::new("").jump(4, 9).apply(&mut vec![1, 0, 0, 0, 104, 7, 104, 8, 99, 99]) {
+            Err(PatchError::TooShortForJump{addr: 4, size: 2}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        let applied = Patch::new("").set(1, &[5]).apply(&mut image).unwrap();
+        image[1] = 6;
+        assert!(applied.revert(&mut image).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Opt-in execution profiling for `Intcode::step`.
+//!
+//! Counts executions per op, per instruction word (which is the op plus its parameter modes)
+//!  and per address, keeps taken/not-taken counts for conditional jumps and treats every taken
+//!  backward jump as a loop iteration. `Profile::report` ranks the hot spots as text,
+//!  `Profile::to_json` has everything for other tools.
+use super::{
+    engine::Observer,
+    error::IntcodeError,
+    instruction::Op,
+    memory::Memory,
+    vm::Intcode,
+    DWord,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// What a conditional jump at one address did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+    /// How often each target was jumped to.
+    pub targets: BTreeMap<usize, u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub executed: u64,
+    pub ops: BTreeMap<Op, u64>,
+    /// Keyed by raw instruction word, e.g. 1001 for `add` with an immediate second parameter.
+    pub words: BTreeMap<DWord, u64>,
+    pub addresses: BTreeMap<usize, u64>,
+    pub branches: BTreeMap<usize, Branch>,
+    /// Iterations of each loop, keyed by the `(target, source)` of its backward jump.
+    pub loops: BTreeMap<(usize, usize), u64>,
+}
+
+/// Sorts by count, highest first, and keeps the first `top`.
+fn ranked<K: Copy>(counts: &BTreeMap<K, u64>, top: usize) -> Vec<(K, u64)> {
+    let mut ranked: Vec<(K, u64)> = counts.iter().map(|(&k, &n)| (k, n)).collect();
+    ranked.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+    ranked.truncate(top);
+    ranked
+}
+
+fn json_counts<K, F: Fn(&K) -> String>(counts: &BTreeMap<K, u64>, key: F) -> String {
+    let entries: Vec<String> = counts.iter().map(|(k, n)| format!("\"{}\":{}", key(k), n)).collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+impl Profile {
+    /// Addresses executed the most.
+    pub fn hot_spots(&self, top: usize) -> Vec<(usize, u64)> {
+        ranked(&self.addresses, top)
+    }
+
+    /// Loops iterated the most, as `(start, end)` address ranges.
+    pub fn hot_loops(&self, top: usize) -> Vec<((usize, usize), u64)> {
+        ranked(&self.loops, top)
+    }
+
+    /// Human readable summary with the `top` entries of every ranking.
+    pub fn report(&self, top: usize) -> String {
+        let share = |n: u64| 100.0 * n as f64 / self.executed.max(1) as f64;
+        let mut out = String::new();
+        writeln!(out, "executed {} instructions", self.executed).unwrap();
+        writeln!(out, "ops:").unwrap();
+        for (op, n) in ranked(&self.ops, top) {
+            writeln!(out, "  {:<6} {:>10} {:>5.1}%", op.mnemonic(), n, share(n)).unwrap();
+        }
+        writeln!(out, "words:").unwrap();
+        for (word, n) in ranked(&self.words, top) {
+            writeln!(out, "  {:<6} {:>10} {:>5.1}%", word, n, share(n)).unwrap();
+        }
+        writeln!(out, "hot spots:").unwrap();
+        for (addr, n) in self.hot_spots(top) {
+            writeln!(out, "  {:04}   {:>10} {:>5.1}%", addr, n, share(n)).unwrap();
+        }
+        writeln!(out, "branches:").unwrap();
+        let branches: BTreeMap<usize, u64> = self.branches.iter()
+            .map(|(&addr, branch)| (addr, branch.taken + branch.not_taken))
+            .collect();
+        for (addr, _) in ranked(&branches, top) {
+            let branch = &self.branches[&addr];
+            writeln!(out, "  {:04} taken {} not taken {}", addr, branch.taken, branch.not_taken).unwrap();
+        }
+        writeln!(out, "loops:").unwrap();
+        for ((start, end), n) in self.hot_loops(top) {
+            writeln!(out, "  {:04}..{:04} {:>10} iterations", start, end, n).unwrap();
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let branches: Vec<String> = self.branches.iter()
+            .map(|(addr, branch)| format!(
+                "\"{}\":{{\"taken\":{},\"not_taken\":{},\"targets\":{}}}",
+                addr, branch.taken, branch.not_taken, json_counts(&branch.targets, usize::to_string),
+            ))
+            .collect();
+        let loops: Vec<String> = self.loops.iter()
+            .map(|((start, end), n)| format!("{{\"start\":{},\"end\":{},\"iterations\":{}}}", start, end, n))
+            .collect();
+        format!(
+            "{{\"executed\":{},\"ops\":{},\"words\":{},\"addresses\":{},\"branches\":{{{}}},\"loops\":[{}]}}",
+            self.executed,
+            json_counts(&self.ops, |op| op.mnemonic().to_owned()),
+            json_counts(&self.words, DWord::to_string),
+            json_counts(&self.addresses, usize::to_string),
+            branches.join(","),
+            loops.join(","),
+        )
+    }
+}
+
+/// Steps an `Intcode` and counts what it executes.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    profile: Profile,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn into_profile(self) -> Profile {
+        self.profile
+    }
+}
+
+impl Observer for Profiler {
+    /// Same as `Intcode::step`, but counts the executed instruction.
+    fn step<M: Memory>(&mut self, vm: &mut Intcode<M>) -> Result<Option<DWord>, IntcodeError> {
+        let pc = vm.pc();
+        let word = vm[pc];
+        let executed = vm.executed();
+        let result = vm.step();
+        if vm.executed() == executed {
+            return result;
+        }
+
+        let op = Op::from_code(word % 100).expect("executed instruction has a valid op");
+        let p = &mut self.profile;
+        p.executed += 1;
+        *p.ops.entry(op).or_default() += 1;
+        *p.words.entry(word).or_default() += 1;
+        *p.addresses.entry(pc).or_default() += 1;
+        if let Op::JumpIfTrue | Op::JumpIfFalse = op {
+            let branch = p.branches.entry(pc).or_default();
+            let target = vm.pc();
+            if target == pc + 3 {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+                *branch.targets.entry(target).or_default() += 1;
+                if target <= pc {
+                    *p.loops.entry((target, pc)).or_default() += 1;
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    #[test]
+    fn quine() -> Result<(), IntcodeError> {
+        let mut profiler = Profiler::new();
+        profiler.run(&mut QUINE.parse::<Intcode>()?)?;
+        let profile = profiler.profile();
+        // 16 rounds of 5 instructions, then the halt.
+        assert_eq!(profile.executed, 81);
+        assert_eq!(profile.ops[&Op::Out], 16);
+        assert_eq!(profile.words[&1001], 16);
+        assert_eq!(profile.branches[&12], Branch{taken: 15, not_taken: 1, targets: vec![(0, 15)].into_iter().collect()});
+        assert_eq!(profile.hot_loops(5), vec![((0, 12), 15)]);
+        assert_eq!(profile.hot_spots(1), vec![(0, 16)]);
+
+        let report = profile.report(3);
+        assert!(report.starts_with("executed 81 instructions\nops:\n  add            16  19.8%\n"));
+        assert!(report.ends_with("loops:\n  0000..0012         15 iterations\n"));
+        assert!(profile.to_json().contains("\"branches\":{\"12\":{\"taken\":15,\"not_taken\":1,\"targets\":{\"0\":15}}}"));
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Runs networks of `Intcode` machines on a single thread.
+//!
+//! Machines are run round-robin in index order, each until it blocks waiting for input or halts,
+//!  with every output routed to the inputs of other machines as described by the `Topology`.
+//! That keeps runs deterministic, unlike a thread per machine.
+use super::{
+    error::IntcodeError,
+    memory::{Memory, Paged},
+    vm::{Intcode, State},
+    DWord,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    /// Machine `i` feeds machine `i + 1`, outputs of the last one only get recorded.
+    Pipeline,
+    /// Like `Pipeline`, but the last machine feeds the first one.
+    Ring,
+    /// Outputs of `from` are sent to every `to` listed for it, in listed order.
+    Graph(Vec<(usize, usize)>),
+    /// Outputs come in packets of a destination address followed by `width` values.
+    /// Machines waiting for input with nothing queued are given `empty` instead.
+    Packets{width: usize, empty: DWord},
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every machine halted.
+    Halted,
+    /// Nothing can make progress: every machine that hasn't halted waits for input nobody will send.
+    Deadlock,
+    /// Packet network went a whole round without sending a packet.
+    Idle,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub outcome: Outcome,
+    /// Everything each machine output so far, routed or not.
+    pub outputs: Vec<Vec<DWord>>,
+    pub states: Vec<State>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scheduler<M: Memory = Paged> {
+    nodes: Vec<Intcode<M>>,
+    topology: Topology,
+    outputs: Vec<Vec<DWord>>,
+    /// Partially output packets, per machine.
+    pending: Vec<Vec<DWord>>,
+    /// Packets addressed outside the network.
+    unrouted: Vec<(DWord, Vec<DWord>)>,
+}
+
+impl<M: Memory> Scheduler<M> {
+    pub fn new(nodes: Vec<Intcode<M>>, topology: Topology) -> Self {
+        let n = nodes.len();
+        Self{nodes, topology, outputs: vec![Vec::new(); n], pending: vec![Vec::new(); n], unrouted: Vec::new()}
+    }
+
+    pub fn nodes(&self) -> &[Intcode<M>] {
+        &self.nodes
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut Intcode<M> {
+        &mut self.nodes[index]
+    }
+
+    /// Takes packets sent to addresses outside the network since the last call.
+    pub fn take_unrouted(&mut self) -> Vec<(DWord, Vec<DWord>)> {
+        std::mem::take(&mut self.unrouted)
+    }
+
+    /// Runs rounds until every machine halted, nothing can make progress or the packet network idles.
+    /// Can be called again afterwards, e.g. after injecting inputs.
+    pub fn run(&mut self) -> Result<Report, IntcodeError> {
+        let outcome = loop {
+            let mut progress = false;
+            let mut sent = false;
+            // Packets still queued for machines that can read them, those sent to halted ones are lost.
+            let mut queued = false;
+            for index in 0..self.nodes.len() {
+                if let Topology::Packets{empty, ..} = self.topology {
+                    let node = &mut self.nodes[index];
+                    if node.state() != State::Halted {
+                        if node.inputs.is_empty() {
+                            node.inputs.push_back(empty);
+                        } else {
+                            queued = true;
+                        }
+                    }
+                }
+                let (stepped, produced) = self.run_node(index)?;
+                progress |= stepped;
+                sent |= produced;
+            }
+
+            if self.nodes.iter().all(|node| node.state() == State::Halted) {
+                break Outcome::Halted;
+            }
+            match self.topology {
+                Topology::Packets{..} if !sent && !queued => break Outcome::Idle,
+                Topology::Packets{..} => (),
+                _ if !progress => break Outcome::Deadlock,
+                _ => (),
+            }
+        };
+        Ok(Report{
+            outcome,
+            outputs: self.outputs.clone(),
+            states: self.nodes.iter().map(Intcode::state).collect(),
+        })
+    }
+
+    /// Runs one machine until it blocks or halts, returns whether it executed anything and produced output.
+    fn run_node(&mut self, index: usize) -> Result<(bool, bool), IntcodeError> {
+        let mut stepped = false;
+        let mut produced = false;
+        loop {
+            match self.nodes[index].step() {
+                Ok(Some(value)) => {
+                    stepped = true;
+                    produced = true;
+                    self.outputs[index].push(value);
+                    self.route(index, value);
+                }
+                Ok(None) => stepped = true,
+                Err(IntcodeError::NeedsInput) | Err(IntcodeError::Halted) => return Ok((stepped, produced)),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn route(&mut self, from: usize, value: DWord) {
+        let n = self.nodes.len();
+        match &self.topology {
+            Topology::Pipeline => {
+                if from + 1 < n {
+                    self.nodes[from + 1].inputs.push_back(value);
+                }
+            }
+            Topology::Ring => self.nodes[(from + 1) % n].inputs.push_back(value),
+            Topology::Graph(edges) => {
+                for &(_, to) in edges.iter().filter(|&&(f, _)| f == from) {
+                    self.nodes[to].inputs.push_back(value);
+                }
+            }
+            Topology::Packets{width, ..} => {
+                let pending = &mut self.pending[from];
+                pending.push(value);
+                if pending.len() == 1 + width {
+                    let payload = pending.split_off(1);
+                    let address = pending.pop().unwrap();
+                    if address >= 0 && (address as usize) < n {
+                        self.nodes[address as usize].inputs.extend(payload);
+                    } else {
+                        self.unrouted.push((address, payload));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads a value, outputs it plus 1, forever.
+    const INCREMENT: &str = "3,11,101,1,11,11,4,11,1105,1,0,0";
+
+    fn nodes(code: &str, n: usize) -> Vec<Intcode> {
+        (0..n).map(|_| code.parse::<Intcode>().unwrap()).collect()
+    }
+
+    #[test]
+    fn pipeline_and_deadlock() -> Result<(), IntcodeError> {
+        let mut scheduler = Scheduler::new(nodes(INCREMENT, 3), Topology::Pipeline);
+        scheduler.node_mut(0).inputs.extend(vec![10, 20]);
+        let report = scheduler.run()?;
+        assert_eq!(report.outcome, Outcome::Deadlock);
+        assert_eq!(report.outputs, vec![vec![11, 21], vec![12, 22], vec![13, 23]]);
+        assert!(report.states.iter().all(|&s| s == State::WaitingForInput));
+        Ok(())
+    }
+
+    #[test]
+    fn ring_until_halt() -> Result<(), IntcodeError> {
+        // Adds its two inputs and halts.
+        let adder = "3,11,3,12,1,11,12,13,4,13,99,0,0,0";
+        let mut scheduler = Scheduler::new(nodes(adder, 2), Topology::Ring);
+        scheduler.node_mut(0).inputs.extend(vec![1, 2]);
+        scheduler.node_mut(1).inputs.push_back(10);
+        let report = scheduler.run()?;
+        assert_eq!(report.outcome, Outcome::Halted);
+        assert_eq!(report.outputs, vec![vec![3], vec![13]]);
+        // Node 0 halted before node 1 answered, the value is left queued.
+        assert_eq!(scheduler.nodes()[0].inputs, vec![13]);
+        Ok(())
+    }
+
+    #[test]
+    fn graph_fan_out() -> Result<(), IntcodeError> {
+        let mut scheduler = Scheduler::new(nodes(INCREMENT, 3), Topology::Graph(vec![(0, 1), (0, 2), (1, 2)]));
+        scheduler.node_mut(0).inputs.push_back(0);
+        let report = scheduler.run()?;
+        assert_eq!(report.outputs, vec![vec![1], vec![2], vec![2, 3]]);
+        Ok(())
+    }
+
+    #[test]
+    fn packets_until_idle() -> Result<(), IntcodeError> {
+        // Reads an address, skips empty reads, then forwards its own address + 1 to that address,
+        //  and whatever it receives afterwards to address 255.
+        let code = "
+            in [rb+100]
+        wait:
+            in [rb+101]
+            eq [rb+101], #-1, [rb+102]
+            jt [rb+102], #wait
+            out [rb+101]
+            add [rb+100], #1, [rb+103]
+            out [rb+103]
+        loop:
+            in [rb+104]
+            eq [rb+104], #-1, [rb+102]
+            jt [rb+102], #loop
+            out #255
+            out [rb+104]
+            jt #1, #loop
+        ";
+        let code = super::super::asm::assemble(code).unwrap();
+        let nodes = (0..2).map(|i| Intcode::new(code.clone(), vec![i])).collect();
+        let mut scheduler = Scheduler::new(nodes, Topology::Packets{width: 1, empty: -1});
+        // Node 0 talks to node 1 and the other way around.
+        scheduler.node_mut(0).inputs.push_back(1);
+        scheduler.node_mut(1).inputs.push_back(0);
+        let report = scheduler.run()?;
+        assert_eq!(report.outcome, Outcome::Idle);
+        assert_eq!(scheduler.take_unrouted(), vec![(255, vec![1]), (255, vec![2])]);
+        Ok(())
+    }
+
+    #[test]
+    fn packets_to_halted_node() -> Result<(), IntcodeError> {
+        // Node 0 sends 5 to node 1, which has already halted, then reads forever.
+        let nodes = vec!["104,1,104,5,3,20,1105,1,4".parse::<Intcode>()?, "99".parse::<Intcode>()?];
+        let mut scheduler = Scheduler::new(nodes, Topology::Packets{width: 1, empty: -1});
+        let report = scheduler.run()?;
+        assert_eq!(report.outcome, Outcome::Idle);
+        assert_eq!(scheduler.nodes()[1].inputs, vec![5]);
+        Ok(())
+    }
+}
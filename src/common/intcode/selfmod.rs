@@ -0,0 +1,185 @@
+//! Opt-in detection of self-modifying code for `Intcode::step`.
+//!
+//! Every executed instruction marks its words as code. Writes landing on code, or on the
+//!  instruction about to be executed next, are recorded with the writing pc and both values.
+//! `Detector::summary` merges them into the code regions that got modified.
+use super::{
+    engine::Observer,
+    error::IntcodeError,
+    instruction::Op,
+    memory::Memory,
+    vm::Intcode,
+    DWord,
+};
+use std::{collections::BTreeSet, fmt};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Code that has been executed before.
+    Executed,
+    /// The instruction right after the write, before its first execution.
+    Pending,
+}
+
+/// One write into code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub pc: usize,
+    pub addr: usize,
+    pub old: DWord,
+    pub new: DWord,
+    pub target: Target,
+}
+
+/// Consecutive modified code addresses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    /// First address past the region.
+    pub end: usize,
+    pub writes: usize,
+    /// Addresses of the instructions that wrote into the region.
+    pub writers: BTreeSet<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub regions: Vec<Region>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "self-modified code regions: {}", self.regions.len())?;
+        for region in self.regions.iter() {
+            let writers: Vec<String> = region.writers.iter().map(|pc| format!("{:04}", pc)).collect();
+            writeln!(f, "  {:04}..{:04} {} writes by {}", region.start, region.end, region.writes, writers.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps an `Intcode` and records writes into its code.
+#[derive(Clone, Debug, Default)]
+pub struct Detector {
+    /// Executed addresses, sparse since code may run anywhere in memory.
+    code: BTreeSet<usize>,
+    writes: Vec<CodeWrite>,
+}
+
+/// Number of words the instruction at `pc` takes, 1 for anything that isn't an instruction.
+fn width<M: Memory>(vm: &Intcode<M>, pc: usize) -> usize {
+    Op::from_code(vm[pc] % 100).map(|op| 1 + op.arity()).unwrap_or(1)
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every write into code so far, in execution order.
+    pub fn writes(&self) -> &[CodeWrite] {
+        &self.writes
+    }
+
+    /// Whether `addr` was executed as part of an instruction.
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.code.contains(&addr)
+    }
+
+    /// Merges recorded writes into modified regions.
+    pub fn summary(&self) -> Summary {
+        let mut writes: Vec<&CodeWrite> = self.writes.iter().collect();
+        writes.sort_by_key(|w| w.addr);
+        let mut regions: Vec<Region> = Vec::new();
+        for write in writes {
+            match regions.last_mut() {
+                Some(region) if write.addr <= region.end => {
+                    region.end = write.addr + 1;
+                    region.writes += 1;
+                    region.writers.insert(write.pc);
+                }
+                _ => regions.push(Region{
+                    start: write.addr,
+                    end: write.addr + 1,
+                    writes: 1,
+                    writers: Some(write.pc).into_iter().collect(),
+                }),
+            }
+        }
+        Summary{regions}
+    }
+}
+
+impl Observer for Detector {
+    /// Same as `Intcode::step`, but records a write into code.
+    fn step<M: Memory>(&mut self, vm: &mut Intcode<M>) -> Result<Option<DWord>, IntcodeError> {
+        let pc = vm.pc();
+        let target = match vm.parse_opcode() {
+            Ok((args, op)) => Op::from_code(op as DWord).and_then(Op::write_param).map(|i| args[i]),
+            Err(_) => None,
+        };
+        let old = target.map(|addr| vm[addr]);
+        let size = width(vm, pc);
+        let executed = vm.executed();
+        let result = vm.step();
+        if vm.executed() == executed {
+            return result;
+        }
+
+        self.code.extend(pc..pc + size);
+        if let (Some(addr), Some(old)) = (target, old) {
+            let next = vm.pc();
+            let target = if self.is_code(addr) {
+                Some(Target::Executed)
+            } else if (next..next + width(vm, next)).contains(&addr) {
+                Some(Target::Pending)
+            } else {
+                None
+            };
+            if let Some(target) = target {
+                self.writes.push(CodeWrite{pc, addr, old, new: vm[addr], target});
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn executed_code() {
+        // Outputs 1, then patches its own `out #1` into `out #2`, `out #3` and so on.
+        let mut vm = "104,1,1001,1,1,1,1105,1,0".parse::<Intcode>().unwrap();
+        let mut detector = Detector::new();
+        for _ in 0..7 {
+            detector.step(&mut vm).unwrap();
+        }
+        assert_eq!(detector.writes()[0], CodeWrite{pc: 2, addr: 1, old: 1, new: 2, target: Target::Executed});
+        assert_eq!(detector.writes().len(), 2);
+        assert_eq!(detector.summary().to_string(), "self-modified code regions: 1\n  0001..0002 2 writes by 0002\n");
+    }
+
+    #[test]
+    fn pending_instruction() -> Result<(), IntcodeError> {
+        // Turns the halt at 4 into `out #7` right before getting there.
+        let mut vm = "1101,104,0,4,99,7,99".parse::<Intcode>()?;
+        let mut detector = Detector::new();
+        assert_eq!(detector.run(&mut vm)?, vec![7]);
+        assert_eq!(detector.writes(), &[CodeWrite{pc: 0, addr: 4, old: 99, new: 104, target: Target::Pending}]);
+        Ok(())
+    }
+
+    #[test]
+    fn far_code() -> Result<(), IntcodeError> {
+        // Writes `out #77` and a halt far away, then jumps there.
+        let far = 1_000_000_000_000;
+        let mut vm = Intcode::new(vec![1101, 104, 0, far, 1101, 77, 0, far + 1, 1101, 99, 0, far + 2, 1105, 1, far], vec![]);
+        let mut detector = Detector::new();
+        assert_eq!(detector.run(&mut vm)?, vec![77]);
+        assert!(detector.is_code(far as usize) && detector.is_code(far as usize + 2));
+        assert!(detector.writes().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! Saving and restoring complete `Intcode` state.
+//!
+//! Snapshots are plain text so they can be diffed and attached to bug reports:
+//!
+//! ```text
+//! intcode-snapshot 1
+//! pc 4
+//! base 1
+//! state ready
+//! executed 3
+//! inputs 5 7
+//! mem 0 109 1 204 -1 1001 100 1 100
+//! mem 100 1
+//! ```
+//!
+//! Memory is stored as runs of consecutive non-zero cells, each starting with its address,
+//!  so a restored VM counts its code as ending at the last of them.
+//! The first line carries the format version, snapshots of any other version are rejected.
+//! Configuration like the overflow policy or budget isn't part of the state and restores to defaults.
+use super::{
+    budget::Budget,
+    error::SnapshotError,
+    memory::Memory,
+    vm::{Intcode, OverflowPolicy, State},
+    DWord,
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+const MAGIC: &str = "intcode-snapshot";
+pub const VERSION: u32 = 1;
+
+impl<M: Memory> Intcode<M> {
+    /// Writes a snapshot of the VM.
+    pub fn save<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{} {}", MAGIC, VERSION)?;
+        writeln!(w, "pc {}", self.pc)?;
+        writeln!(w, "base {}", self.base)?;
+        writeln!(w, "state {}", match self.state {
+            State::Ready => "ready",
+            State::WaitingForInput => "waiting",
+            State::Halted => "halted",
+        })?;
+        writeln!(w, "executed {}", self.executed)?;
+        write!(w, "inputs")?;
+        for input in self.inputs.iter() {
+            write!(w, " {}", input)?;
+        }
+        writeln!(w)?;
+
+        let mut next = None;
+        for (addr, value) in self.memory.nonzero_cells() {
+            if next != Some(addr) {
+                if next.is_some() {
+                    writeln!(w)?;
+                }
+                write!(w, "mem {}", addr)?;
+            }
+            write!(w, " {}", value)?;
+            next = Some(addr + 1);
+        }
+        if next.is_some() {
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a VM back from a snapshot written by `save`.
+    pub fn restore<R: BufRead>(r: R) -> Result<Self, SnapshotError> {
+        let mut lines = r.lines();
+        let header = lines.next().ok_or(SnapshotError::NotASnapshot)??;
+        let mut header = header.split_whitespace();
+        if header.next() != Some(MAGIC) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = header.next().unwrap_or("").to_owned();
+        if version.parse() != Ok(VERSION) {
+            return Err(SnapshotError::UnsupportedVersion{found: version, expected: VERSION});
+        }
+
+        let (mut pc, mut base, mut state, mut inputs) = (None, None, None, None);
+        let mut executed = 0;
+        let mut memory = M::default();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let number = index + 2;
+            let malformed = |reason: &str| SnapshotError::Malformed{line: number, reason: reason.to_owned()};
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let values: Vec<&str> = words.collect();
+            let numbers = || values.iter()
+                .map(|v| v.parse::<DWord>())
+                .collect::<Result<Vec<DWord>, _>>()
+                .map_err(|_| malformed("expected numbers"));
+            match key {
+                "pc" | "base" | "executed" if values.len() != 1 => return Err(malformed("expected a single value")),
+                "pc" => pc = Some(values[0].parse::<usize>().map_err(|_| malformed("bad pc"))?),
+                "base" => base = Some(numbers()?[0]),
+                "executed" => executed = values[0].parse().map_err(|_| malformed("bad instruction count"))?,
+                "state" => state = Some(match values.as_slice() {
+                    ["ready"] => State::Ready,
+                    ["waiting"] => State::WaitingForInput,
+                    ["halted"] => State::Halted,
+                    _ => return Err(malformed("unknown state")),
+                }),
+                "inputs" => inputs = Some(numbers()?.into_iter().collect::<VecDeque<DWord>>()),
+                "mem" => {
+                    let run = numbers()?;
+                    match run.split_first() {
+                        Some((&start, cells)) if start >= 0 => {
+                            for (i, &value) in cells.iter().enumerate() {
+                                memory.insert(start as usize + i, value);
+                            }
+                        }
+                        _ => return Err(malformed("expected start address")),
+                    }
+                }
+                _ => return Err(malformed("unknown entry")),
+            }
+        }
+
+        let loaded = memory.nonzero_cells().last().map(|(addr, _)| addr + 1).unwrap_or(0);
+        Ok(Intcode{
+            loaded,
+            pc: pc.ok_or(SnapshotError::Missing{field: "pc"})?,
+            base: base.ok_or(SnapshotError::Missing{field: "base"})?,
+            state: state.ok_or(SnapshotError::Missing{field: "state"})?,
+            inputs: inputs.ok_or(SnapshotError::Missing{field: "inputs"})?,
+            executed,
+            overflow: OverflowPolicy::default(),
+            budget: Budget::default(),
+            memory,
+        })
+    }
+
+    /// Writes a snapshot of the VM to a file.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.save(&mut w)?;
+        w.flush()
+    }
+
+    /// Reads a VM from a snapshot file.
+    pub fn restore_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::restore(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<(), SnapshotError> {
+        let mut vm = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99".parse::<Intcode>().unwrap();
+        vm.inputs.extend(vec![5, -7]);
+        vm.nth(3);
+
+        let mut saved = Vec::new();
+        vm.save(&mut saved)?;
+        let text = String::from_utf8(saved).unwrap();
+        assert!(text.starts_with("intcode-snapshot 1\npc 4\nbase 4\nstate ready\nexecuted 17\ninputs 5 -7\nmem 0 109"));
+        assert!(text.ends_with("\nmem 15 99\nmem 100 3\n"));
+
+        let mut restored = Intcode::restore(text.as_bytes())?;
+        assert_eq!(restored, vm);
+        assert_eq!(restored.next().unwrap().unwrap(), vm.next().unwrap().unwrap());
+        Ok(())
+    }
+
+    fn restore(text: &str) -> Result<Intcode, SnapshotError> {
+        Intcode::restore(text.as_bytes())
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        match restore("intcode-snapshot 0\npc 0\n") {
+            Err(SnapshotError::UnsupportedVersion{..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match restore("1,2,3") {
+            Err(SnapshotError::NotASnapshot) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match restore("intcode-snapshot 1\npc 0\nbase x\n") {
+            Err(SnapshotError::Malformed{line: 3, ..}) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,575 @@
+//! Symbolic execution of Intcode and a small solver for the inputs it depends on.
+//!
+//! Chosen memory cells and inputs hold symbols with a bounded range instead of values.
+//! `add` and `mul` fold into linear combinations of symbols where they can, anything else builds
+//!  an expression tree of at most `MAX_SIZE` nodes. Branches on symbolic conditions fork the
+//!  path, and every path keeps the conditions it took. Reads from symbolic addresses give an
+//!  opaque `Unknown`, anything else symbolic where a concrete value is needed ends the path.
+//! The solver handles linear equalities by narrowing each symbol to the values that can still
+//!  balance the rest, enumerates whatever isn't pinned down that way and checks every condition.
+use super::{
+    instruction::{Mode, Op},
+    DWord,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::TryFrom,
+    fmt,
+    rc::Rc,
+};
+
+/// Nodes an expression may have before the path building it gets stuck.
+pub const MAX_SIZE: usize = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Mul,
+    Lt,
+    Eq,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    /// Constants, symbols and sums of their multiples, as long as every number fits into a `DWord`.
+    Linear(Linear),
+    /// Read from a symbolic address.
+    Unknown,
+    /// Anything else, with the number of nodes in it.
+    Node(BinOp, Rc<(Expr, Expr)>, usize),
+}
+
+impl From<DWord> for Expr {
+    fn from(value: DWord) -> Self {
+        Expr::Linear(Linear{constant: i128::from(value), terms: BTreeMap::new()})
+    }
+}
+
+impl Expr {
+    /// Symbol by index, as returned by `Symbolic::symbol_at` and `Symbolic::symbol_input`.
+    pub fn symbol(var: usize) -> Expr {
+        Expr::Linear(Linear{constant: 0, terms: Some((var, 1)).into_iter().collect()})
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Expr::Node(_, _, size) => *size,
+            _ => 1,
+        }
+    }
+
+    /// Folds what can be folded, fails if the result would be larger than `MAX_SIZE`.
+    fn combine(op: BinOp, a: Expr, b: Expr) -> Result<Expr, End> {
+        let folded = match (op, &a, &b) {
+            (BinOp::Add, Expr::Linear(x), Expr::Linear(y)) => Some(x.clone().plus(y, 1)),
+            (BinOp::Mul, Expr::Linear(x), Expr::Linear(y)) if x.terms.is_empty() => Some(y.clone().scaled(x.constant)),
+            (BinOp::Mul, Expr::Linear(x), Expr::Linear(y)) if y.terms.is_empty() => Some(x.clone().scaled(y.constant)),
+            _ => match (op, a.constant(), b.constant()) {
+                (BinOp::Lt, Some(x), Some(y)) => return Ok(Expr::from((x < y) as DWord)),
+                (BinOp::Eq, Some(x), Some(y)) => return Ok(Expr::from((x == y) as DWord)),
+                (BinOp::Add, Some(0), _) | (BinOp::Mul, Some(1), _) => return Ok(b),
+                (BinOp::Add, _, Some(0)) | (BinOp::Mul, _, Some(1)) => return Ok(a),
+                (BinOp::Mul, Some(0), _) | (BinOp::Mul, _, Some(0)) => return Ok(Expr::from(0)),
+                _ => None,
+            },
+        };
+        match folded {
+            Some(linear) if linear.fits() => Ok(Expr::Linear(linear)),
+            _ => {
+                let size = 1 + a.size() + b.size();
+                if size > MAX_SIZE {
+                    return Err(End::Stuck("expression too large"));
+                }
+                Ok(Expr::Node(op, Rc::new((a, b)), size))
+            }
+        }
+    }
+
+    pub fn constant(&self) -> Option<DWord> {
+        match self {
+            Expr::Linear(linear) if linear.terms.is_empty() => Some(linear.constant as DWord),
+            _ => None,
+        }
+    }
+
+    /// Value under `assignment`, None if it overflows or depends on something unknown.
+    pub fn eval(&self, assignment: &[DWord]) -> Option<DWord> {
+        Some(match self {
+            Expr::Linear(linear) => {
+                let mut value = linear.constant;
+                for (&var, &c) in linear.terms.iter() {
+                    value = value.checked_add(c.checked_mul(i128::from(*assignment.get(var)?))?)?;
+                }
+                DWord::try_from(value).ok()?
+            }
+            Expr::Unknown => return None,
+            Expr::Node(op, operands, _) => {
+                let (a, b) = (operands.0.eval(assignment)?, operands.1.eval(assignment)?);
+                match op {
+                    BinOp::Add => a.checked_add(b)?,
+                    BinOp::Mul => a.checked_mul(b)?,
+                    BinOp::Lt => (a < b) as DWord,
+                    BinOp::Eq => (a == b) as DWord,
+                }
+            }
+        })
+    }
+
+    /// The expression as a linear combination of symbols, if it is one.
+    pub fn linear(&self) -> Option<&Linear> {
+        match self {
+            Expr::Linear(linear) => Some(linear),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Linear(linear) => {
+                let mut parts: Vec<String> = linear.terms.iter()
+                    .map(|(var, c)| if *c == 1 { format!("x{}", var) } else { format!("{}*x{}", c, var) })
+                    .collect();
+                if linear.constant != 0 || parts.is_empty() {
+                    parts.push(linear.constant.to_string());
+                }
+                write!(f, "{}", parts.join(" + "))
+            }
+            Expr::Unknown => write!(f, "?"),
+            Expr::Node(op, operands, _) => {
+                let symbol = match op {
+                    BinOp::Add => "+",
+                    BinOp::Mul => "*",
+                    BinOp::Lt => "<",
+                    BinOp::Eq => "==",
+                };
+                write!(f, "({}) {} ({})", operands.0, symbol, operands.1)
+            }
+        }
+    }
+}
+
+/// `constant + sum(coefficient * symbol)`, wide enough that solving doesn't overflow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Linear {
+    pub constant: i128,
+    pub terms: BTreeMap<usize, i128>,
+}
+
+impl Linear {
+    fn plus(mut self, other: &Linear, sign: i128) -> Linear {
+        self.constant += sign * other.constant;
+        for (&var, &coefficient) in other.terms.iter() {
+            *self.terms.entry(var).or_default() += sign * coefficient;
+        }
+        self.terms.retain(|_, c| *c != 0);
+        self
+    }
+
+    fn scaled(mut self, factor: i128) -> Linear {
+        self.constant *= factor;
+        self.terms.values_mut().for_each(|c| *c *= factor);
+        self.terms.retain(|_, c| *c != 0);
+        self
+    }
+
+    /// Whether every number fits into a `DWord`, which keeps folding them from overflowing.
+    fn fits(&self) -> bool {
+        let fits = |n: &i128| DWord::try_from(*n).is_ok();
+        fits(&self.constant) && self.terms.values().all(fits)
+    }
+}
+
+/// A branch condition a path took: `expr` was nonzero or not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub expr: Expr,
+    pub nonzero: bool,
+}
+
+impl Condition {
+    fn holds(&self, assignment: &[DWord]) -> bool {
+        self.expr.eval(assignment).map(|value| (value != 0) == self.nonzero) == Some(true)
+    }
+
+    /// The condition as `linear == 0`, if it is an equality.
+    fn equality(&self) -> Option<Linear> {
+        match (&self.expr, self.nonzero) {
+            (Expr::Node(BinOp::Eq, operands, _), true) => Some(operands.0.linear()?.clone().plus(operands.1.linear()?, -1)),
+            (expr, false) => expr.linear().cloned(),
+            _ => None,
+        }
+    }
+}
+
+/// Why a path stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    /// Reached the address being searched for.
+    Reached,
+    NeedsInput,
+    StepLimit,
+    /// Needed a concrete value where there was a symbolic one, or hit an invalid instruction.
+    Stuck(&'static str),
+}
+
+/// One way through the program.
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub end: End,
+    pub pc: usize,
+    pub memory: BTreeMap<usize, Expr>,
+    pub outputs: Vec<Expr>,
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Clone, Debug)]
+struct State {
+    pc: usize,
+    base: DWord,
+    memory: BTreeMap<usize, Expr>,
+    inputs: VecDeque<Expr>,
+    outputs: Vec<Expr>,
+    conditions: Vec<Condition>,
+    steps: usize,
+}
+
+impl State {
+    fn read(&self, addr: usize) -> Expr {
+        self.memory.get(&addr).cloned().unwrap_or_else(|| Expr::from(0))
+    }
+
+    fn concrete(&self, addr: usize, what: &'static str) -> Result<DWord, End> {
+        self.read(addr).constant().ok_or(End::Stuck(what))
+    }
+
+    /// Address parameter `i` of the instruction at pc refers to, None if it's symbolic.
+    fn address(&self, mode: Mode, i: usize) -> Result<Option<usize>, End> {
+        let at = self.pc + 1 + i;
+        let addr = match (mode, self.read(at).constant()) {
+            (Mode::Immediate, _) => return Ok(Some(at)),
+            (_, None) => return Ok(None),
+            (Mode::Position, Some(value)) => value,
+            (Mode::Relative, Some(value)) => self.base.checked_add(value).ok_or(End::Stuck("overflow"))?,
+        };
+        if addr < 0 {
+            return Err(End::Stuck("negative address"));
+        }
+        Ok(Some(addr as usize))
+    }
+
+    /// Executes one instruction, returns the other side of a symbolic branch.
+    fn step(&mut self) -> Result<Option<State>, End> {
+        let word = self.concrete(self.pc, "symbolic instruction")?;
+        let op = Op::from_code(word % 100).filter(|_| word >= 0).ok_or(End::Stuck("unknown opcode"))?;
+        let mut modes = Vec::new();
+        let mut digits = word / 100;
+        for i in 0..op.arity() {
+            let mode = Mode::from_digit(digits % 10).ok_or(End::Stuck("invalid mode"))?;
+            if mode == Mode::Immediate && op.write_param() == Some(i) {
+                return Err(End::Stuck("immediate write"));
+            }
+            modes.push(mode);
+            digits /= 10;
+        }
+        let arg = |i: usize| -> Result<Expr, End> {
+            Ok(self.address(modes[i], i)?.map(|addr| self.read(addr)).unwrap_or(Expr::Unknown))
+        };
+        let target = |i: usize| -> Result<usize, End> {
+            self.address(modes[i], i)?.ok_or(End::Stuck("write to symbolic address"))
+        };
+        let mut next = self.pc + 1 + op.arity();
+        let mut fork = None;
+        match op {
+            Op::Add | Op::Mul | Op::LessThan | Op::Equals => {
+                let (a, b, to) = (arg(0)?, arg(1)?, target(2)?);
+                let op = match op {
+                    Op::Add => BinOp::Add,
+                    Op::Mul => BinOp::Mul,
+                    Op::LessThan => BinOp::Lt,
+                    _ => BinOp::Eq,
+                };
+                let value = Expr::combine(op, a, b)?;
+                self.memory.insert(to, value);
+            }
+            Op::In => {
+                let to = target(0)?;
+                let value = self.inputs.pop_front().ok_or(End::NeedsInput)?;
+                self.memory.insert(to, value);
+            }
+            Op::Out => {
+                let value = arg(0)?;
+                self.outputs.push(value);
+            }
+            Op::JumpIfTrue | Op::JumpIfFalse => {
+                let (cond, to) = (arg(0)?, arg(1)?);
+                let to = to.constant().ok_or(End::Stuck("symbolic jump target"))?;
+                let jump = |to: DWord| if to < 0 { Err(End::Stuck("negative address")) } else { Ok(to as usize) };
+                let when = op == Op::JumpIfTrue;
+                match cond.constant() {
+                    Some(value) if (value != 0) == when => next = jump(to)?,
+                    Some(_) => (),
+                    None => {
+                        let mut taken = self.clone();
+                        taken.conditions.push(Condition{expr: cond.clone(), nonzero: when});
+                        taken.pc = jump(to)?;
+                        taken.steps += 1;
+                        fork = Some(taken);
+                        self.conditions.push(Condition{expr: cond, nonzero: !when});
+                    }
+                }
+            }
+            Op::AdjustBase => {
+                let by = arg(0)?.constant().ok_or(End::Stuck("symbolic base"))?;
+                self.base = self.base.checked_add(by).ok_or(End::Stuck("overflow"))?;
+            }
+            Op::Halt => return Err(End::Halted),
+        }
+        self.pc = next;
+        self.steps += 1;
+        Ok(fork)
+    }
+
+    fn into_path(self, end: End) -> Path {
+        Path{end, pc: self.pc, memory: self.memory, outputs: self.outputs, conditions: self.conditions}
+    }
+}
+
+/// Runs a program with symbolic cells and inputs along every path it can take.
+#[derive(Clone, Debug)]
+pub struct Symbolic {
+    initial: State,
+    domains: Vec<(DWord, DWord)>,
+    max_steps: usize,
+    max_paths: usize,
+}
+
+impl Symbolic {
+    pub fn new(code: &[DWord]) -> Self {
+        let memory = code.iter().enumerate().map(|(addr, &value)| (addr, Expr::from(value))).collect();
+        let initial = State{pc: 0, base: 0, memory, inputs: VecDeque::new(), outputs: Vec::new(), conditions: Vec::new(), steps: 0};
+        Symbolic{initial, domains: Vec::new(), max_steps: 100_000, max_paths: 1000}
+    }
+
+    /// Makes the cell at `addr` a symbol ranging over `lo..=hi`, returns its index.
+    pub fn symbol_at(&mut self, addr: usize, lo: DWord, hi: DWord) -> usize {
+        self.domains.push((lo, hi));
+        self.initial.memory.insert(addr, Expr::symbol(self.domains.len() - 1));
+        self.domains.len() - 1
+    }
+
+    /// Queues a symbolic input ranging over `lo..=hi`, returns its index.
+    pub fn symbol_input(&mut self, lo: DWord, hi: DWord) -> usize {
+        self.domains.push((lo, hi));
+        self.initial.inputs.push_back(Expr::symbol(self.domains.len() - 1));
+        self.domains.len() - 1
+    }
+
+    /// Queues a concrete input.
+    pub fn input(&mut self, value: DWord) -> &mut Self {
+        self.initial.inputs.push_back(Expr::from(value));
+        self
+    }
+
+    /// Caps instructions per path and the number of paths, 100000 and 1000 by default.
+    pub fn limits(&mut self, steps: usize, paths: usize) -> &mut Self {
+        self.max_steps = steps;
+        self.max_paths = paths;
+        self
+    }
+
+    /// Every path through the program, each ending where it stopped or at `stop_at`.
+    pub fn explore(&self, stop_at: Option<usize>) -> Vec<Path> {
+        let mut pending = vec![self.initial.clone()];
+        let mut paths = Vec::new();
+        while let Some(mut state) = pending.pop() {
+            if paths.len() + pending.len() >= self.max_paths {
+                paths.push(state.into_path(End::StepLimit));
+                continue;
+            }
+            let end = loop {
+                if Some(state.pc) == stop_at {
+                    break End::Reached;
+                }
+                if state.steps >= self.max_steps {
+                    break End::StepLimit;
+                }
+                match state.step() {
+                    Ok(fork) => pending.extend(fork),
+                    Err(end) => break end,
+                }
+            };
+            paths.push(state.into_path(end));
+        }
+        paths
+    }
+
+    /// Symbol values that leave `target` in the cell at `addr` when the program halts.
+    pub fn solve_memory(&self, addr: usize, target: DWord) -> Option<Vec<DWord>> {
+        self.solve_paths(|path| path.memory.get(&addr).cloned().or_else(|| Some(Expr::from(0))), target)
+    }
+
+    /// Symbol values that make output number `index` equal `target`.
+    pub fn solve_output(&self, index: usize, target: DWord) -> Option<Vec<DWord>> {
+        self.solve_paths(|path| path.outputs.get(index).cloned(), target)
+    }
+
+    /// Symbol values that make the program reach the instruction at `pc`.
+    pub fn solve_reach(&self, pc: usize) -> Option<Vec<DWord>> {
+        self.explore(Some(pc)).into_iter()
+            .filter(|path| path.end == End::Reached)
+            .find_map(|path| solve(&path.conditions, &self.domains))
+    }
+
+    fn solve_paths<F: Fn(&Path) -> Option<Expr>>(&self, goal: F, target: DWord) -> Option<Vec<DWord>> {
+        self.explore(None).into_iter()
+            .filter(|path| path.end == End::Halted)
+            .find_map(|path| {
+                let mut conditions = path.conditions.clone();
+                let goal = goal(&path)?;
+                let size = goal.size() + 2;
+                let goal = Expr::Node(BinOp::Eq, Rc::new((goal, Expr::from(target))), size);
+                conditions.push(Condition{expr: goal, nonzero: true});
+                solve(&conditions, &self.domains)
+            })
+    }
+}
+
+fn floor_div(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    -floor_div(-a, b)
+}
+
+/// Finds symbol values within `domains` that satisfy every condition.
+pub fn solve(conditions: &[Condition], domains: &[(DWord, DWord)]) -> Option<Vec<DWord>> {
+    let equalities: Vec<Linear> = conditions.iter().filter_map(Condition::equality).collect();
+    let mut assignment = vec![None; domains.len()];
+    search(conditions, &equalities, domains, &mut assignment)
+}
+
+fn search(conditions: &[Condition], equalities: &[Linear], domains: &[(DWord, DWord)], assignment: &mut Vec<Option<DWord>>) -> Option<Vec<DWord>> {
+    // Substitutes what's assigned, a fully assigned equality that doesn't balance fails early.
+    let mut open = None;
+    for equality in equalities {
+        let mut rest = Linear{constant: equality.constant, terms: BTreeMap::new()};
+        for (&var, &c) in equality.terms.iter() {
+            match assignment[var] {
+                Some(value) => rest.constant += c * i128::from(value),
+                None => { rest.terms.insert(var, c); }
+            }
+        }
+        if rest.terms.is_empty() {
+            if rest.constant != 0 {
+                return None;
+            }
+        } else if open.is_none() {
+            open = Some(rest);
+        }
+    }
+
+    let (var, candidates) = match open {
+        Some(open) => {
+            // c * x must make up for whatever the other terms can't.
+            let (&var, &c) = open.terms.iter().max_by_key(|(_, c)| c.abs()).expect("open equality has terms");
+            let (mut low, mut high) = (open.constant, open.constant);
+            for (&other, &d) in open.terms.iter().filter(|&(&other, _)| other != var) {
+                let (lo, hi) = domains[other];
+                let (a, b) = (d * i128::from(lo), d * i128::from(hi));
+                low += a.min(b);
+                high += a.max(b);
+            }
+            let (from, to) = if c > 0 { (ceil_div(-high, c), floor_div(-low, c)) } else { (ceil_div(-low, c), floor_div(-high, c)) };
+            let (lo, hi) = domains[var];
+            let (from, to) = (from.max(i128::from(lo)), to.min(i128::from(hi)));
+            if from > to {
+                return None;
+            }
+            (var, from as DWord..=to as DWord)
+        }
+        None => match assignment.iter().position(Option::is_none) {
+            Some(var) => (var, domains[var].0..=domains[var].1),
+            None => {
+                let values: Vec<DWord> = assignment.iter().map(|value| value.unwrap()).collect();
+                return if conditions.iter().all(|c| c.holds(&values)) { Some(values) } else { None };
+            }
+        },
+    };
+    for value in candidates {
+        assignment[var] = Some(value);
+        if let Some(solution) = search(conditions, equalities, domains, assignment) {
+            return Some(solution);
+        }
+    }
+    assignment[var] = None;
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{error::IntcodeError, memory::Memory, vm::Intcode};
+
+    #[test]
+    fn linear_memory() -> Result<(), IntcodeError> {
+        // Like day 2: reads through the symbols, then [0] = ([1] + [2]) * 5 - 3.
+        let code = vec![1, 0, 0, 3, 1, 1, 2, 3, 2, 3, 17, 0, 1001, 0, -3, 0, 99, 5];
+        let mut symbolic = Symbolic::new(&code);
+        let noun = symbolic.symbol_at(1, 0, 99);
+        let verb = symbolic.symbol_at(2, 0, 99);
+        let paths = symbolic.explore(None);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].memory[&0].to_string(), "5*x0 + 5*x1 + -3");
+
+        let solution = symbolic.solve_memory(0, 492).unwrap();
+        assert_eq!(solution[noun] + solution[verb], 99);
+        let mut vm = Intcode::new(code, vec![]);
+        vm.memory_mut().insert(1, solution[noun]);
+        vm.memory_mut().insert(2, solution[verb]);
+        vm.by_ref().for_each(drop);
+        assert_eq!(vm[0], 492);
+        assert_eq!(symbolic.solve_memory(0, 1000), None);
+        Ok(())
+    }
+
+    #[test]
+    fn branches() {
+        // Outputs 1 if the input is 42, halts otherwise.
+        let code = vec![3, 20, 1008, 20, 42, 21, 1005, 21, 10, 99, 104, 1, 99];
+        let mut symbolic = Symbolic::new(&code);
+        symbolic.symbol_input(0, 100);
+        assert_eq!(symbolic.explore(None).len(), 2);
+        assert_eq!(symbolic.solve_reach(10), Some(vec![42]));
+        assert_eq!(symbolic.solve_output(0, 1), Some(vec![42]));
+        assert_eq!(symbolic.solve_reach(9).map(|s| s[0] != 42), Some(true));
+
+        // The whole range is only walked as far as needed.
+        let mut symbolic = Symbolic::new(&code);
+        symbolic.symbol_input(DWord::MIN, DWord::MAX);
+        assert_eq!(symbolic.solve_reach(10), Some(vec![42]));
+        assert_eq!(symbolic.solve_reach(9), Some(vec![DWord::MIN]));
+    }
+
+    #[test]
+    fn long_loops() {
+        // Adds 1 to the input 30000 times.
+        let mut code = vec![3, 100, 1001, 100, 1, 100, 1001, 101, -1, 101, 1005, 101, 2, 4, 100, 99];
+        code.resize(102, 0);
+        code[101] = 30_000;
+        let mut symbolic = Symbolic::new(&code);
+        symbolic.symbol_input(0, 100);
+        assert_eq!(symbolic.solve_output(0, 30_042), Some(vec![42]));
+
+        // Squares it instead.
+        code[2] = 2;
+        code[4] = 100;
+        let mut symbolic = Symbolic::new(&code);
+        symbolic.symbol_input(0, 100);
+        assert_eq!(symbolic.explore(None)[0].end, End::Stuck("expression too large"));
+    }
+}
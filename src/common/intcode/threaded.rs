@@ -0,0 +1,351 @@
+//! Pre-decoded execution engine with the semantics of `Intcode::step`.
+//!
+//! Every instruction is decoded once into a handler function plus its parameters and cached by
+//!  address, so executing it again skips decoding and allocation altogether.
+//! Writes landing on a cached instruction drop it from the cache, which keeps self-modifying
+//!  programs correct. Code past `CACHED` is decoded every time it runs, so the cache stays small.
+//! Anything out of the ordinary, like errors, waiting for input, halting, overflow or a memory
+//!  budget that has to see every write, is handed to the wrapped interpreter,
+//!  so states and errors are exactly the same as there.
+use super::{
+    engine::Engine,
+    error::IntcodeError,
+    instruction::{Mode, Op},
+    memory::{Memory, Paged},
+    vm::{Intcode, State},
+    DWord,
+};
+use std::{collections::VecDeque, fmt};
+
+/// Addresses below this have their instructions cached.
+pub const CACHED: usize = 1 << 20;
+
+type Handler<M> = fn(&mut Threaded<M>, &Decoded<M>) -> Result<Option<DWord>, IntcodeError>;
+
+struct Decoded<M: Memory> {
+    handler: Handler<M>,
+    modes: [Mode; 3],
+    values: [DWord; 3],
+    size: usize,
+}
+
+impl<M: Memory> Clone for Decoded<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: Memory> Copy for Decoded<M> {}
+
+pub struct Threaded<M: Memory = Paged> {
+    vm: Intcode<M>,
+    cache: Vec<Option<Decoded<M>>>,
+}
+
+impl<M: Memory> From<Intcode<M>> for Threaded<M> {
+    fn from(vm: Intcode<M>) -> Self {
+        Threaded{vm, cache: Vec::new()}
+    }
+}
+
+impl<M: Memory> Clone for Threaded<M> {
+    fn clone(&self) -> Self {
+        Threaded{vm: self.vm.clone(), cache: self.cache.clone()}
+    }
+}
+
+impl<M: Memory> fmt::Debug for Threaded<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cached = self.cache.iter().filter(|d| d.is_some()).count();
+        f.debug_struct("Threaded").field("vm", &self.vm).field("cached", &cached).finish()
+    }
+}
+
+impl<M: Memory> Threaded<M> {
+    pub fn vm(&self) -> &Intcode<M> {
+        &self.vm
+    }
+
+    /// Gives access to the wrapped VM, which drops everything decoded so far.
+    pub fn vm_mut(&mut self) -> &mut Intcode<M> {
+        self.cache.clear();
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> Intcode<M> {
+        self.vm
+    }
+
+    pub fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
+        if self.vm.state == State::Halted || self.vm.budget.tracks_memory() || self.vm.budget.check().is_err() {
+            return self.fallback();
+        }
+        let pc = self.vm.pc;
+        let decoded = match self.cache.get(pc) {
+            Some(Some(decoded)) => *decoded,
+            _ => match self.decode(pc) {
+                Some(decoded) if pc >= CACHED => decoded,
+                Some(decoded) => {
+                    if pc >= self.cache.len() {
+                        self.cache.resize_with(pc + 1, || None);
+                    }
+                    self.cache[pc] = Some(decoded);
+                    decoded
+                }
+                None => return self.fallback(),
+            },
+        };
+        (decoded.handler)(self, &decoded)
+    }
+
+    /// Decodes the instruction at `pc`, or returns None to leave it to the interpreter.
+    fn decode(&self, pc: usize) -> Option<Decoded<M>> {
+        let word = self.vm[pc];
+        if word < 0 {
+            return None;
+        }
+        let op = Op::from_code(word % 100)?;
+        let handler: Handler<M> = match op {
+            Op::Add => add,
+            Op::Mul => mul,
+            Op::In => input,
+            Op::Out => output,
+            Op::JumpIfTrue => jump_if_true,
+            Op::JumpIfFalse => jump_if_false,
+            Op::LessThan => less_than,
+            Op::Equals => equals,
+            Op::AdjustBase => adjust_base,
+            Op::Halt => return None,
+        };
+        let mut modes = [Mode::Position; 3];
+        let mut values = [0; 3];
+        let mut digits = word / 100;
+        for i in 0..op.arity() {
+            modes[i] = Mode::from_digit(digits % 10)?;
+            if modes[i] == Mode::Immediate && op.write_param() == Some(i) {
+                return None;
+            }
+            values[i] = self.vm[pc + 1 + i];
+            digits /= 10;
+        }
+        Some(Decoded{handler, modes, values, size: 1 + op.arity()})
+    }
+
+    /// Runs the instruction on the interpreter. It may have written anywhere, so the cache goes.
+    fn fallback(&mut self) -> Result<Option<DWord>, IntcodeError> {
+        let result = self.vm.step();
+        if result.is_ok() {
+            self.cache.clear();
+        }
+        result
+    }
+
+    fn address(&self, decoded: &Decoded<M>, i: usize) -> Option<usize> {
+        let value = decoded.values[i];
+        let addr = match decoded.modes[i] {
+            Mode::Position => value,
+            Mode::Immediate => return Some(self.vm.pc + 1 + i),
+            Mode::Relative => self.vm.base.checked_add(value)?,
+        };
+        if addr < 0 {
+            return None;
+        }
+        Some(addr as usize)
+    }
+
+    fn read(&self, decoded: &Decoded<M>, i: usize) -> Option<DWord> {
+        match decoded.modes[i] {
+            Mode::Immediate => Some(decoded.values[i]),
+            _ => self.address(decoded, i).map(|addr| self.vm[addr]),
+        }
+    }
+
+    fn write(&mut self, addr: usize, value: DWord) {
+        self.vm[addr] = value;
+        let end = (addr + 1).min(self.cache.len());
+        for at in addr.saturating_sub(3)..end {
+            if let Some(decoded) = self.cache[at] {
+                if at + decoded.size > addr {
+                    self.cache[at] = None;
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self, pc: usize, output: Option<DWord>) -> Result<Option<DWord>, IntcodeError> {
+        self.vm.pc = pc;
+        self.vm.executed += 1;
+        self.vm.budget.spend(output.is_some(), None);
+        Ok(output)
+    }
+}
+
+fn add<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    match (t.read(d, 0), t.read(d, 1), t.address(d, 2)) {
+        (Some(a), Some(b), Some(to)) => match a.checked_add(b) {
+            Some(value) => {
+                t.write(to, value);
+                t.advance(t.vm.pc + 4, None)
+            }
+            None => t.fallback(),
+        },
+        _ => t.fallback(),
+    }
+}
+
+fn mul<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    match (t.read(d, 0), t.read(d, 1), t.address(d, 2)) {
+        (Some(a), Some(b), Some(to)) => match a.checked_mul(b) {
+            Some(value) => {
+                t.write(to, value);
+                t.advance(t.vm.pc + 4, None)
+            }
+            None => t.fallback(),
+        },
+        _ => t.fallback(),
+    }
+}
+
+fn input<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    match (t.address(d, 0), t.vm.inputs.is_empty()) {
+        (Some(to), false) => {
+            let value = t.vm.inputs.pop_front().unwrap();
+            t.write(to, value);
+            t.vm.state = State::Ready;
+            t.advance(t.vm.pc + 2, None)
+        }
+        _ => t.fallback(),
+    }
+}
+
+fn output<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    match t.read(d, 0) {
+        Some(value) => t.advance(t.vm.pc + 2, Some(value)),
+        None => t.fallback(),
+    }
+}
+
+fn jump<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>, when: bool) -> Result<Option<DWord>, IntcodeError> {
+    match (t.read(d, 0), t.read(d, 1)) {
+        (Some(cond), Some(target)) if (cond != 0) == when => match target {
+            target if target >= 0 => t.advance(target as usize, None),
+            _ => t.fallback(),
+        },
+        (Some(_), Some(_)) => t.advance(t.vm.pc + 3, None),
+        _ => t.fallback(),
+    }
+}
+
+fn jump_if_true<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    jump(t, d, true)
+}
+
+fn jump_if_false<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    jump(t, d, false)
+}
+
+fn compare<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>, f: fn(DWord, DWord) -> bool) -> Result<Option<DWord>, IntcodeError> {
+    match (t.read(d, 0), t.read(d, 1), t.address(d, 2)) {
+        (Some(a), Some(b), Some(to)) => {
+            t.write(to, if f(a, b) { 1 } else { 0 });
+            t.advance(t.vm.pc + 4, None)
+        }
+        _ => t.fallback(),
+    }
+}
+
+fn less_than<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    compare(t, d, |a, b| a < b)
+}
+
+fn equals<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    compare(t, d, |a, b| a == b)
+}
+
+fn adjust_base<M: Memory>(t: &mut Threaded<M>, d: &Decoded<M>) -> Result<Option<DWord>, IntcodeError> {
+    match t.read(d, 0).and_then(|value| t.vm.base.checked_add(value)) {
+        Some(base) => {
+            t.vm.base = base;
+            t.advance(t.vm.pc + 2, None)
+        }
+        None => t.fallback(),
+    }
+}
+
+impl<M: Memory> Iterator for Threaded<M> {
+    type Item = Result<DWord, IntcodeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step() {
+                Ok(Some(o)) => return Some(Ok(o)),
+                Err(IntcodeError::Halted) => return None,
+                Err(e) => return Some(Err(e)),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<M: Memory> Engine for Threaded<M> {
+    fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
+        Threaded::step(self)
+    }
+
+    fn state(&self) -> State {
+        self.vm.state()
+    }
+
+    fn pc(&self) -> usize {
+        self.vm.pc()
+    }
+
+    fn executed(&self) -> u64 {
+        self.vm.executed()
+    }
+
+    fn peek(&self, addr: usize) -> DWord {
+        self.vm[addr]
+    }
+
+    fn inputs_mut(&mut self) -> &mut VecDeque<DWord> {
+        &mut self.vm.inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn both(code: &str, inputs: Vec<DWord>) -> (Intcode, Threaded) {
+        let mut vm = code.parse::<Intcode>().unwrap();
+        vm.inputs.extend(inputs);
+        (vm.clone(), Threaded::from(vm))
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let compare = "3,9,8,9,10,9,4,9,99,-1,8";
+        for &(code, ref inputs) in &[(quine, vec![]), (compare, vec![8]), (compare, vec![7]), ("3,0,4,0,77", vec![])] {
+            let (mut vm, mut threaded) = both(code, inputs.clone());
+            loop {
+                let (a, b) = (vm.step(), threaded.step());
+                assert_eq!(format!("{:?}", a), format!("{:?}", b));
+                assert_eq!(&vm, threaded.vm());
+                if a.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn self_modifying() -> Result<(), IntcodeError> {
+        // Outputs 1, then patches its own `out #1` into `out #2` and runs it again.
+        let (mut vm, mut threaded) = both("104,1,1101,1,1,1,1105,1,0", vec![]);
+        let expected: Vec<DWord> = vm.by_ref().take(3).collect::<Result<_, _>>()?;
+        assert_eq!(expected, vec![1, 2, 2]);
+        assert_eq!(threaded.by_ref().take(3).collect::<Result<Vec<_>, _>>()?, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Opt-in execution tracing for `Intcode::step`.
+//!
+//! Records are written as they happen, either as compact text lines:
+//!
+//! ```text
+//! #2 pc=4 1001 add args=[100,6,100] read=[0,1] write=[100]=1 rb=1
+//! ```
+//!
+//! or as JSON lines, handy for diffing two runs with external tools.
+use super::{
+    engine::Observer,
+    error::IntcodeError,
+    instruction::Op,
+    memory::Memory,
+    vm::{Intcode, State},
+    DWord,
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    io::Write,
+    ops::Range,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Compact,
+    Json,
+}
+
+/// Everything one executed instruction did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// How many instructions the tracer executed before this one.
+    pub index: u64,
+    pub pc: usize,
+    /// Raw instruction word, modes included.
+    pub opcode: DWord,
+    pub op: Op,
+    /// Resolved address of every parameter.
+    pub args: Vec<usize>,
+    /// Values of the parameters that are read, in order.
+    pub reads: Vec<DWord>,
+    /// Address and new value of the written cell.
+    pub write: Option<(usize, DWord)>,
+    pub output: Option<DWord>,
+    /// Relative base after the instruction.
+    pub base: DWord,
+}
+
+impl TraceRecord {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"index\":{},\"pc\":{},\"opcode\":{},\"op\":\"{}\",\"args\":[{}],\"reads\":[{}],\"write\":{},\"output\":{},\"base\":{}}}",
+            self.index,
+            self.pc,
+            self.opcode,
+            self.op.mnemonic(),
+            join(&self.args),
+            join(&self.reads),
+            match self.write {
+                Some((addr, value)) => format!("{{\"addr\":{},\"value\":{}}}", addr, value),
+                None => "null".to_owned(),
+            },
+            self.output.map(|o| o.to_string()).unwrap_or_else(|| "null".to_owned()),
+            self.base,
+        )
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} pc={} {} {}", self.index, self.pc, self.opcode, self.op.mnemonic())?;
+        if !self.args.is_empty() {
+            write!(f, " args=[{}]", join(&self.args))?;
+        }
+        if !self.reads.is_empty() {
+            write!(f, " read=[{}]", join(&self.reads))?;
+        }
+        if let Some((addr, value)) = self.write {
+            write!(f, " write=[{}]={}", addr, value)?;
+        }
+        if let Some(output) = self.output {
+            write!(f, " out={}", output)?;
+        }
+        write!(f, " rb={}", self.base)
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(T::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Steps an `Intcode` and streams a record of every executed instruction into a writer.
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: Format,
+    range: Option<Range<usize>>,
+    ops: Option<HashSet<Op>>,
+    limit: Option<u64>,
+    executed: u64,
+    written: u64,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W, format: Format) -> Self {
+        Self{writer, format, range: None, ops: None, limit: None, executed: 0, written: 0}
+    }
+
+    /// Only trace instructions whose pc falls into `range`.
+    pub fn range(&mut self, range: Range<usize>) -> &mut Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Only trace the given ops.
+    pub fn ops(&mut self, ops: &[Op]) -> &mut Self {
+        self.ops = Some(ops.iter().copied().collect());
+        self
+    }
+
+    /// Stop writing records after `limit` of them, execution itself isn't affected.
+    pub fn limit(&mut self, limit: u64) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Number of instructions executed through this tracer.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn emit(&mut self, record: &TraceRecord) -> Result<(), IntcodeError> {
+        let wanted = self.range.as_ref().map(|r| r.contains(&record.pc)).unwrap_or(true)
+            && self.ops.as_ref().map(|ops| ops.contains(&record.op)).unwrap_or(true)
+            && self.limit.map(|limit| self.written < limit).unwrap_or(true);
+        if !wanted {
+            return Ok(());
+        }
+        self.written += 1;
+        match self.format {
+            Format::Compact => writeln!(self.writer, "{}", record)?,
+            Format::Json => writeln!(self.writer, "{}", record.to_json())?,
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Observer for Tracer<W> {
+    /// Same as `Intcode::step`, but writes a trace record for the executed instruction.
+    fn step<M: Memory>(&mut self, vm: &mut Intcode<M>) -> Result<Option<DWord>, IntcodeError> {
+        let pc = vm.pc();
+        let opcode = vm[pc];
+        let decoded = match vm.parse_opcode() {
+            Ok((mut args, op)) => Op::from_code(op as DWord).map(|op| {
+                args.truncate(op.arity());
+                let reads = args.iter()
+                    .enumerate()
+                    .filter(|&(i, _)| Some(i) != op.write_param())
+                    .map(|(_, &addr)| vm[addr])
+                    .collect::<Vec<DWord>>();
+                (op, args, reads)
+            }),
+            Err(_) => None,
+        };
+        let was_halted = vm.state() == State::Halted;
+        let result = vm.step();
+
+        if let Some((op, args, reads)) = decoded {
+            let executed = match result {
+                Ok(_) => true,
+                Err(IntcodeError::Halted) => op == Op::Halt && !was_halted,
+                Err(_) => false,
+            };
+            if executed {
+                let write = op.write_param().map(|i| (args[i], vm[args[i]]));
+                let record = TraceRecord{
+                    index: self.executed,
+                    pc,
+                    opcode,
+                    op,
+                    args,
+                    reads,
+                    write,
+                    output: *result.as_ref().unwrap_or(&None),
+                    base: vm.base(),
+                };
+                self.executed += 1;
+                self.emit(&record)?;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    fn trace(code: &str, inputs: Vec<DWord>, setup: impl Fn(&mut Tracer<Vec<u8>>), format: Format) -> String {
+        let mut vm = code.parse::<Intcode>().unwrap();
+        vm.inputs.extend(inputs);
+        let mut tracer = Tracer::new(Vec::new(), format);
+        setup(&mut tracer);
+        tracer.run(&mut vm).unwrap();
+        String::from_utf8(tracer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn compact() {
+        let out = trace("3,9,8,9,10,9,4,9,99,-1,8", vec![8], |_| (), Format::Compact);
+        assert_eq!(out, "\
+#0 pc=0 3 in args=[9] write=[9]=8 rb=0
+#1 pc=2 8 eq args=[9,10,9] read=[8,8] write=[9]=1 rb=0
+#2 pc=6 4 out args=[9] read=[1] out=1 rb=0
+#3 pc=8 99 hlt rb=0
+");
+    }
+
+    #[test]
+    fn json() {
+        let out = trace(QUINE, vec![], |t| { t.limit(2); }, Format::Json);
+        assert_eq!(out, "\
+{\"index\":0,\"pc\":0,\"opcode\":109,\"op\":\"arb\",\"args\":[1],\"reads\":[1],\"write\":null,\"output\":null,\"base\":1}
+{\"index\":1,\"pc\":2,\"opcode\":204,\"op\":\"out\",\"args\":[0],\"reads\":[109],\"write\":null,\"output\":109,\"base\":1}
+");
+    }
+
+    #[test]
+    fn filters() {
+        let out = trace(QUINE, vec![], |t| { t.ops(&[Op::Add]).range(0..10).limit(3); }, Format::Compact);
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.lines().all(|l| l.contains(" add ")));
+    }
+}
@@ -1,6 +1,12 @@
-use super::{error::IntcodeError, DWord};
+use super::{
+    budget::Budget,
+    error::{Fault, IntcodeError},
+    instruction::{Mode, Op},
+    memory::{Memory, Paged},
+    DWord,
+};
 use std::{
-    collections::{BTreeMap, VecDeque},
+    collections::VecDeque,
     ops::{Index, IndexMut},
 };
 
@@ -11,55 +17,145 @@ pub enum State {
     Halted,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Intcode {
-    pc: usize,
-    state: State,
-    base: DWord,
-    pub memory: BTreeMap<usize, DWord>,
+/// What arithmetic instructions do when the result doesn't fit into a `DWord`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Panic,
+    Wrap,
+    Saturate,
+    /// Fail the instruction with `IntcodeError::Overflow`, leaving the VM as it was.
+    Error,
+}
+
+// Deriving it needs `#[default]` on the variant, which the nightly this crate builds on doesn't have.
+#[allow(clippy::derivable_impls)]
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Error
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Intcode<M: Memory = Paged> {
+    pub(super) pc: usize,
+    pub(super) state: State,
+    pub(super) base: DWord,
+    pub(super) executed: u64,
+    pub(super) overflow: OverflowPolicy,
+    pub(super) budget: Budget,
+    /// Length of the code the VM was loaded with, trailing zeroes included.
+    pub(super) loaded: usize,
+    pub memory: M,
     pub inputs: VecDeque<DWord>,
 }
 
+/// The budget is bookkeeping for the current run and isn't compared.
+/// Neither is the loaded length, memories compare by their nonzero cells anyway.
+impl<M: Memory> PartialEq for Intcode<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pc == other.pc
+            && self.state == other.state
+            && self.base == other.base
+            && self.executed == other.executed
+            && self.overflow == other.overflow
+            && self.memory == other.memory
+            && self.inputs == other.inputs
+    }
+}
+
+impl<M: Memory> Eq for Intcode<M> {}
+
 impl Intcode {
     pub fn new(memory: Vec<DWord>, inputs: Vec<DWord>) -> Self {
+        Intcode::with_backend(memory, inputs)
+    }
+}
+
+impl<M: Memory> Intcode<M> {
+    /// Same as `new`, but with memory stored in the given backend.
+    pub fn with_backend(memory: Vec<DWord>, inputs: Vec<DWord>) -> Self {
         Intcode {
-            memory: memory.into_iter().enumerate().collect(),
+            loaded: memory.len(),
+            memory: M::from_code(memory),
             inputs: VecDeque::from(inputs),
             pc: 0,
             base: 0,
+            executed: 0,
+            overflow: OverflowPolicy::default(),
+            budget: Budget::default(),
             state: State::Ready,
         }
     }
-    
-    /// Returns current memory of the VM.
+
+    /// Returns current memory of the VM indexed by address.
+    /// It's as long as the loaded code, or longer if anything past it isn't 0.
     pub fn memory(self) -> Vec<DWord> {
-        self.memory.values().cloned().collect()
+        let mut memory = Vec::new();
+        for (addr, value) in self.memory.nonzero_cells() {
+            memory.resize(addr, 0);
+            memory.push(value);
+        }
+        if memory.len() < self.loaded {
+            memory.resize(self.loaded, 0);
+        }
+        memory
     }
 
     /// Returns mutable memory of the VM.
-    pub fn memory_mut(&mut self) -> &mut BTreeMap<usize, DWord> {
+    pub fn memory_mut(&mut self) -> &mut M {
         &mut self.memory
     }
 
     /// Returns current state of the VM.
-    pub fn state(self) -> State {
+    pub fn state(&self) -> State {
         self.state
     }
+
+    /// Returns address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Returns current relative base.
+    pub fn base(&self) -> DWord {
+        self.base
+    }
+
+    /// Sets what arithmetic overflow does, `OverflowPolicy::Error` by default.
+    pub fn overflow(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.overflow = policy;
+        self
+    }
+
+    /// Returns the execution budget to set or raise limits on, unlimited by default.
+    pub fn budget_mut(&mut self) -> &mut Budget {
+        &mut self.budget
+    }
+
+    /// Returns number of instructions executed so far.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
     
     /// MAGICAL SMOKE MACHINE, read the docs @ https://adventofcode.com/2019/day/{2,5,7,9}.
     pub fn step(&mut self) -> Result<Option<DWord>, IntcodeError> {
         if self.state == State::Halted {
             return Err(IntcodeError::Halted);
         }
+        self.budget.check().map_err(|limit| IntcodeError::BudgetExhausted{limit, fault: self.fault()})?;
         let (args, op) = self.parse_opcode()?;
+        let write = Op::from_code(op as DWord).and_then(Op::write_param).map(|i| args[i]);
+        self.budget.check_write(write).map_err(|limit| IntcodeError::BudgetExhausted{limit, fault: self.fault()})?;
         let mut output = None;
         let new_pc = match op {
             1 => {
-                self[args[2]] = self[args[0]] + self[args[1]];
+                let (a, b) = (self[args[0]], self[args[1]]);
+                self[args[2]] = self.arithmetic(a.checked_add(b), a.wrapping_add(b), a.saturating_add(b))?;
                 self.pc + 4
             }
             2 => {
-                self[args[2]] = self[args[0]] * self[args[1]];
+                let (a, b) = (self[args[0]], self[args[1]]);
+                self[args[2]] = self.arithmetic(a.checked_mul(b), a.wrapping_mul(b), a.saturating_mul(b))?;
                 self.pc + 4
             }
             3 => match self.inputs.pop_front() {
@@ -79,14 +175,14 @@ impl Intcode {
             }
             5 => {
                 if self[args[0]] != 0 {
-                    self[args[1]] as usize
+                    self.address(self[args[1]])?
                 } else {
                     self.pc + 3
                 }
             }
             6 => {
                 if self[args[0]] == 0 {
-                    self[args[1]] as usize
+                    self.address(self[args[1]])?
                 } else {
                     self.pc + 3
                 }
@@ -108,47 +204,80 @@ impl Intcode {
                 self.pc + 4
             }
             9 => {
-                self.base += self[args[0]];
+                let (a, b) = (self.base, self[args[0]]);
+                self.base = self.arithmetic(a.checked_add(b), a.wrapping_add(b), a.saturating_add(b))?;
                 self.pc + 2
             }
             99 => {
                 self.state = State::Halted;
+                self.executed += 1;
+                self.budget.spend(false, None);
                 return Err(IntcodeError::Halted);
             }
-            _ => return Err(IntcodeError::UnknownOpcode{opcode: self[self.pc]}),
+            _ => return Err(IntcodeError::UnknownOpcode{opcode: self[self.pc], fault: self.fault()}),
         };
         self.pc = new_pc;
+        self.executed += 1;
+        self.budget.spend(output.is_some(), write);
         Ok(output)
     }
 
-    fn parse_opcode(&self) -> Result<(Vec<usize>, usize), IntcodeError> {
-        let mut opcode = *self.memory.get(&self.pc).unwrap_or(&0) as usize;
-        let op = opcode % 100;
-        opcode /= 100;
-        let mut modes = Vec::with_capacity(4);
-        while opcode > 0 {
-            modes.push(opcode % 10);
-            opcode /= 10;
+    /// Resolves the address of every parameter of the instruction at pc.
+    pub(crate) fn parse_opcode(&self) -> Result<(Vec<usize>, usize), IntcodeError> {
+        let word = self[self.pc];
+        let op = match Op::from_code(word % 100) {
+            Some(op) if word >= 0 => op,
+            _ => return Err(IntcodeError::UnknownOpcode{opcode: word, fault: self.fault()}),
+        };
+        let mut modes = word / 100;
+        let mut args = Vec::with_capacity(op.arity());
+        for param in 0..op.arity() {
+            let at = self.pc + 1 + param;
+            let addr = match Mode::from_digit(modes % 10) {
+                Some(Mode::Position) => self.address(self[at])?,
+                Some(Mode::Immediate) if op.write_param() == Some(param) => {
+                    return Err(IntcodeError::ImmediateWrite{param, fault: self.fault()});
+                }
+                Some(Mode::Immediate) => at,
+                Some(Mode::Relative) => self.address(self.checked(self.base.checked_add(self[at]))?)?,
+                None => return Err(IntcodeError::InvalidMode{mode: modes % 10, param, fault: self.fault()}),
+            };
+            args.push(addr);
+            modes /= 10;
+        }
+        Ok((args, op.code() as usize))
+    }
+
+    /// Describes the instruction at pc for an error.
+    fn fault(&self) -> Box<Fault> {
+        let start = self.pc.saturating_sub(4);
+        let memory = (start..self.pc + 8).map(|addr| self[addr]).collect();
+        Fault::new(self.pc, self[self.pc], self.base, self.executed, start, memory)
+    }
+
+    fn address(&self, addr: DWord) -> Result<usize, IntcodeError> {
+        if addr < 0 {
+            return Err(IntcodeError::NegativeAddress{addr, fault: self.fault()});
+        }
+        Ok(addr as usize)
+    }
+
+    fn checked(&self, value: Option<DWord>) -> Result<DWord, IntcodeError> {
+        value.ok_or_else(|| IntcodeError::Overflow{fault: self.fault()})
+    }
+
+    /// Picks the result of an arithmetic instruction according to the overflow policy.
+    fn arithmetic(&self, checked: Option<DWord>, wrapped: DWord, saturated: DWord) -> Result<DWord, IntcodeError> {
+        match self.overflow {
+            OverflowPolicy::Error => self.checked(checked),
+            OverflowPolicy::Panic => Ok(checked.unwrap_or_else(|| panic!("arithmetic overflow {}", self.fault()))),
+            OverflowPolicy::Wrap => Ok(wrapped),
+            OverflowPolicy::Saturate => Ok(saturated),
         }
-        modes.resize(4, 0);
-        let args = modes
-            .into_iter()
-            .enumerate()
-            .map(|(mut i, mode)| {
-                i += 1;
-                Ok(match mode {
-                    0 => self[i + self.pc] as usize,
-                    1 => i + self.pc,
-                    2 => (self.base + self[i + self.pc]) as usize,
-                    _ => return Err(IntcodeError::InvalidMode{mode: self[self.pc]}),
-                })
-            })
-            .collect::<Result<Vec<usize>, IntcodeError>>()?;
-        Ok((args, op))
     }
 }
 
-impl Iterator for Intcode {
+impl<M: Memory> Iterator for Intcode<M> {
     type Item = Result<DWord, IntcodeError>;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -163,28 +292,88 @@ impl Iterator for Intcode {
 }
 
 
-impl Index<usize> for Intcode {
+impl<M: Memory> Index<usize> for Intcode<M> {
     type Output = DWord;
 
     fn index(&self, index: usize) -> &Self::Output {
-        self.memory.get(&index).unwrap_or(&0)
+        self.memory.get(index).unwrap_or(&0)
     }
 }
 
-impl IndexMut<usize> for Intcode {
+impl<M: Memory> IndexMut<usize> for Intcode<M> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        self.memory.entry(index).or_default()
+        self.memory.get_mut(index)
     }
 }
 
-use std::num::ParseIntError;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl From<&str> for Intcode {
-    fn from(s: &str) -> Self {
-        let code: Vec<DWord> = s
-            .split_terminator(',')
-            .map(|x| x.parse())
-            .collect::<Result<Vec<DWord>, ParseIntError>>().unwrap();
-        Intcode::new(code.to_vec(), Vec::new())
+    fn fail(code: &str) -> IntcodeError {
+        let mut vm = code.parse::<Intcode>().unwrap();
+        loop {
+            if let Err(e) = vm.step() {
+                return e;
+            }
+        }
+    }
+
+    #[test]
+    fn memory_by_address() {
+        let mut vm = Intcode::new(vec![1, 2, 0], vec![]);
+        vm[5000] = 7;
+        let memory = vm.memory();
+        assert_eq!((memory.len(), &memory[..3], memory[5000]), (5001, &[1, 2, 0][..], 7));
+        // Trailing zeroes of the code are kept.
+        assert_eq!(Intcode::new(vec![1, 2, 0, 0], vec![]).memory(), vec![1, 2, 0, 0]);
+    }
+
+    #[test]
+    fn fault_context() {
+        let e = fail("109,5,1101,1,2,0,77,0,0");
+        assert_eq!(e.to_string(), "unknown opcode `77` at pc=6 word=77 modes=[] rb=5 after 2 instructions\n0002: 1101 1 2 0 >77< 0 0 0 0 0 0 0");
+        match e {
+            IntcodeError::UnknownOpcode{opcode: 77, fault} => assert_eq!((fault.pc, fault.executed), (6, 2)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        match fail("3001,0,0,0,99") {
+            IntcodeError::InvalidMode{mode: 3, param: 1, fault} => assert_eq!(fault.modes, vec![0, 3, 0]),
+            other => panic!("unexpected {:?}", other),
+        }
+        match fail("11101,1,1,0,99") {
+            IntcodeError::ImmediateWrite{param: 2, ..} => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match fail("4,-3,99") {
+            IntcodeError::NegativeAddress{addr: -3, ..} => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match fail("1105,1,-1") {
+            IntcodeError::NegativeAddress{addr: -1, ..} => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match fail("1102,9223372036854775807,2,0,99") {
+            IntcodeError::Overflow{fault} => assert_eq!(fault.executed, 0),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let code = "1102,9223372036854775807,2,9,4,9,99,0,0,0";
+        let run = |policy| {
+            let mut vm = code.parse::<Intcode>().unwrap();
+            vm.overflow(policy);
+            vm.collect::<Result<Vec<DWord>, IntcodeError>>()
+        };
+        assert_eq!(run(OverflowPolicy::Wrap).unwrap(), vec![-2]);
+        assert_eq!(run(OverflowPolicy::Saturate).unwrap(), vec![DWord::MAX]);
+        assert!(run(OverflowPolicy::Error).is_err());
+        assert!(std::panic::catch_unwind(|| run(OverflowPolicy::Panic)).is_err());
     }
 }
@@ -1,3 +1,2 @@
 pub mod intcode;
-pub mod sif;
-pub mod intcode_old;
\ No newline at end of file
+pub mod sif;
\ No newline at end of file
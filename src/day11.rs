@@ -14,11 +14,10 @@
 //! There's just one problem: you don't have an emergency hull painting robot.
 
 use std::collections::HashMap;
-use crate::common::intcode_old::IntcodeVM;
-use std::str::FromStr;
+use crate::common::intcode::{vm::Intcode, device::{Control, Device, Runner}, error::LoadError};
 use failure::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum Direction {
     Up, 
     Down,
@@ -26,6 +25,14 @@ enum Direction {
     Right, 
 }
 
+// Deriving it needs `#[default]` on the variant, which the nightly this crate builds on doesn't have.
+#[allow(clippy::derivable_impls)]
+impl Default for Direction {
+    fn default() -> Self {
+        Direction::Up
+    }
+}
+
 impl Direction {
     fn turn_left(&self) -> Self {
         match *self {
@@ -44,7 +51,7 @@ impl Direction {
         }
     }
 }
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
 struct Point {
     x: i32,
     y: i32,
@@ -61,21 +68,33 @@ impl Point {
     }
 }
 
+#[derive(Default)]
 struct Painter {
     canvas: HashMap<Point, bool>,
-    vm: IntcodeVM,
     dir: Direction,
     cur: Point,
+    /// Whether the next output is a turn rather than a color.
+    turning: bool,
 }
 
-impl From<IntcodeVM> for Painter {
-    fn from(vm: IntcodeVM) -> Self { 
-        Self{
-            canvas: HashMap::new(),
-            vm: vm,
-            dir: Direction::Up,
-            cur: Point{x: 0, y: 0},
+impl Device for Painter {
+    fn input(&mut self) -> Option<i64> {
+        Some(self.canvas.get(&self.cur).copied().unwrap_or(false) as i64)
+    }
+
+    fn output(&mut self, value: i64) -> Control {
+        if self.turning {
+            self.dir = match value {
+                0 => self.dir.turn_left(),
+                1 => self.dir.turn_right(),
+                _ => panic!("shouldn't happen..."),
+            };
+            self.cur.apply_direction(&self.dir);
+        } else {
+            self.canvas.insert(self.cur, value != 0);
         }
+        self.turning = !self.turning;
+        Control::Continue
     }
 }
 
@@ -92,37 +111,6 @@ impl Painter {
         self.cur
     }
 
-    fn execute(&mut self) -> Result<(), Error> {
-        let mut local_vm = self.vm.clone();
-        let (input, output) = local_vm.io();
-        let bot_thread = std::thread::spawn(move || {
-            local_vm.execute()
-        });
-
-        loop {
-            let color = self.canvas.get(&self.cur).copied().unwrap_or(false);
-            input.send(Some(color as i64))?;
-            if let Ok(Some(new_color)) = output.recv() {
-                self.canvas.insert(self.cur, new_color != 0);
-                if let Ok(Some(new_dir)) = output.recv() {
-                    self.dir = match new_dir {
-                        0 => self.dir.turn_left(),
-                        1 => self.dir.turn_right(),
-                        _ => panic!("shouldn't happen..."),
-                    };
-                    self.cur.apply_direction(&self.dir);
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
-
-        bot_thread.join().expect("couldn't join vm thread")?;
-        Ok(())
-    }
-
     pub fn canvas_bounds(&self) -> (Point, Point) {
         let min_x = self.canvas.keys().map(|&p| p.x).min().unwrap_or(0);
         let max_x = self.canvas.keys().map(|&p| p.x).max().unwrap_or(0);
@@ -149,8 +137,8 @@ impl Painter {
 }
 
 #[aoc_generator(day11)]
-fn input_generator(input: &str) -> IntcodeVM {
-    IntcodeVM::from_str(input).unwrap()
+fn input_generator(input: &str) -> Result<Intcode, LoadError> {
+    input.parse()
 }
 
 // You'll need to build a new emergency hull painting robot. 
@@ -183,11 +171,11 @@ fn input_generator(input: &str) -> IntcodeVM {
 // How many panels does it paint at least once?
 // 
 // Your puzzle answer was 1894.
-#[aoc(day11, part1, IntcodeVM)]
-fn solve_part1_intcode(vm: &IntcodeVM) -> Result<usize, Error> {
-    let mut p = Painter::from(vm.clone());
-    p.execute()?;
-    Ok(p.canvas().len())
+#[aoc(day11, part1, Device)]
+fn solve_part1_device(vm: &Intcode) -> Result<usize, Error> {
+    let mut runner = Runner::new(vm.clone(), Painter::default());
+    runner.run()?;
+    Ok(runner.device.canvas().len())
 }
 
 // You're not sure what it's trying to paint,
@@ -207,11 +195,12 @@ fn solve_part1_intcode(vm: &IntcodeVM) -> Result<usize, Error> {
 //  what registration identifier does it paint on your hull?
 // 
 // Your puzzle answer was JKZLZJBH.
-#[aoc(day11, part2, IntcodeVM)]
-fn solve_part2_intcode(vm: &IntcodeVM) -> Result<String, Error> {
-    let mut p = Painter::from(vm.clone());
+#[aoc(day11, part2, Device)]
+fn solve_part2_device(vm: &Intcode) -> Result<String, Error> {
+    let mut p = Painter::default();
     let bot = p.bot_position();
     p.canvas_mut().entry(bot).or_insert(true);
-    p.execute()?;
-    Ok(p.dump_canvas())
+    let mut runner = Runner::new(vm.clone(), p);
+    runner.run()?;
+    Ok(runner.device.dump_canvas())
 }
@@ -11,13 +11,58 @@
 //! Surely, it won't be hard to build your own 
 //!  - the care package even comes with schematics.
 
-use std::cmp::Ordering;
-use crate::common::intcode::{vm::Intcode, error::IntcodeError};
+use std::{cmp::Ordering, collections::HashMap};
+use crate::common::intcode::{
+    vm::Intcode,
+    device::{Control, Device, Runner},
+    engine::Engine,
+    error::LoadError,
+    patch::Patches,
+    threaded::Threaded,
+};
 use failure::Error;
 
+/// Draws tiles, keeps the score and holds the joystick towards the ball.
+#[derive(Default)]
+struct Arcade {
+    screen: HashMap<(i64, i64), i64>,
+    pending: Vec<i64>,
+    score: i64,
+    ballx: i64,
+    paddlex: i64,
+}
+
+impl Device for Arcade {
+    fn input(&mut self) -> Option<i64> {
+        Some(match self.ballx.cmp(&self.paddlex) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        })
+    }
+
+    fn output(&mut self, value: i64) -> Control {
+        self.pending.push(value);
+        if let [x, y, tile] = self.pending[..] {
+            self.pending.clear();
+            if x == -1 {
+                self.score = tile;
+            } else {
+                self.screen.insert((x, y), tile);
+                if tile == 3 {
+                    self.paddlex = x;
+                } else if tile == 4 {
+                    self.ballx = x;
+                }
+            }
+        }
+        Control::Continue
+    }
+}
+
 #[aoc_generator(day13)]
-fn input_generator(input: &str) -> Intcode {
-    Intcode::from(input)
+fn input_generator(input: &str) -> Result<Intcode, LoadError> {
+    input.parse()
 }
 
 // The arcade cabinet runs Intcode software like the 
@@ -40,12 +85,10 @@ fn input_generator(input: &str) -> Intcode {
 // 
 // Your puzzle answer was 270.
 #[aoc(day13, part1, Base)]
-fn solve_part1_base(vm: &Intcode) -> usize {
-    let all: Vec<_> = vm.clone().collect::<Result<_, IntcodeError>>().unwrap();
-    all.chunks(3)
-        .map(|chunk| chunk[2])
-        .filter(|&tile| tile == 2)
-        .count()
+fn solve_part1_base(vm: &Intcode) -> Result<usize, Error> {
+    let mut runner = Runner::new(vm.clone(), Arcade::default());
+    runner.run()?;
+    Ok(runner.device.screen.values().filter(|&&tile| tile == 2).count())
 }
 
 // The game didn't run because you didn't put in any quarters. 
@@ -72,48 +115,27 @@ fn solve_part1_base(vm: &Intcode) -> usize {
 #[aoc(day13, part2, Base)]
 fn solve_part2_base(vm: &Intcode) -> Result<i64, Error> {
     let mut local_vm = vm.clone();
-    local_vm.memory_mut().insert(0, 2);
-
-    let mut score = 0;
-    let mut ballx = 0;
-    let mut paddlex = 0;
+    free_play(&mut local_vm)?;
+    play(local_vm)
+}
 
-    loop {
-        let mut tiles = Vec::new();
-        while let Some(Ok(v)) = local_vm.next() {
-            tiles.push(v);
-        }
+#[aoc(day13, part2, Threaded)]
+fn solve_part2_threaded(vm: &Intcode) -> Result<i64, Error> {
+    let mut local_vm = vm.clone();
+    free_play(&mut local_vm)?;
+    play(Threaded::from(local_vm))
+}
 
-        for chunk in tiles.chunks(3) {
-            let (x, _, tile) = (chunk[0], chunk[1], chunk[2]);
-            if x == -1 {
-                score = tile;
-            } else if tile == 3 {
-                paddlex = x;
-            } else if tile == 4 {
-                ballx = x;
-            }
-        }
-        
-        match local_vm.next() {
-            Some(Err(IntcodeError::NeedsInput)) => {
-                local_vm.inputs.push_back(
-                    match ballx.cmp(&paddlex) {
-                        Ordering::Less => -1,
-                        Ordering::Equal => 0,
-                        Ordering::Greater => 1,
-                    }
-                )
-            }
-            None => {
-                break;
-            }
-            Some(Err(e)) => {
-                return Err(Error::from(e));
-            }
-            _ => unreachable!(),
-        }
-    }
+/// Inserts the quarters, the patch lives next to the puzzle input.
+fn free_play(vm: &mut Intcode) -> Result<(), Error> {
+    let patches: Patches = include_str!("../input/2019/day13.patch").parse()?;
+    patches.get("free-play")?.apply(vm)?;
+    Ok(())
+}
 
-    Ok(score)
-}
\ No newline at end of file
+/// Plays the game to the end by keeping the paddle under the ball, returns the final score.
+fn play<E: Engine>(local_vm: E) -> Result<i64, Error> {
+    let mut runner = Runner::new(local_vm, Arcade::default());
+    runner.run()?;
+    Ok(runner.device.score)
+}
@@ -18,7 +18,8 @@
 //! By running that program, you can direct the repair droid 
 //!  to the oxygen system and fix the problem.
 
-use crate::common::intcode::{vm::Intcode};
+use crate::common::intcode::{vm::Intcode, device::{Control, Device, Runner}, error::LoadError};
+use failure::{err_msg, format_err, Error};
 use std::collections::HashMap;
 
 const NORTH: i8 = 1;
@@ -32,9 +33,49 @@ const WALL: i8 = 1;
 const OXYGEN: i8 = 2;
 const SPACE: i8 = 3;
 
+/// Moves one step per command and stops the runner once it reports the status.
+#[derive(Clone, Default)]
+struct Droid {
+    pos: (i64, i64),
+    command: Option<i8>,
+    /// Reported status, or the word the program sent when no command was pending.
+    status: Option<Result<i64, i64>>,
+}
+
+impl Device for Droid {
+    fn input(&mut self) -> Option<i64> {
+        self.command.map(i64::from)
+    }
+
+    fn output(&mut self, value: i64) -> Control {
+        self.status = Some(match self.command.take() {
+            Some(command) => {
+                if value != 0 {
+                    self.pos = translate(&self.pos, command);
+                }
+                Ok(value)
+            }
+            None => Err(value),
+        });
+        Control::Stop
+    }
+}
+
+/// Moves a copy of the droid one step in `dir`, returns it with the reported status.
+fn probe(droid: &Runner<Droid>, dir: i8) -> Result<(Runner<Droid>, i64), Error> {
+    let mut new = droid.clone();
+    new.device.command = Some(dir);
+    new.run()?;
+    match new.device.status.take() {
+        Some(Ok(status)) => Ok((new, status)),
+        Some(Err(value)) => Err(format_err!("droid sent {} without a command", value)),
+        None => Err(err_msg("droid halted without reporting a status")),
+    }
+}
+
 #[aoc_generator(day15)]
-fn input_generator(input: &str) -> Intcode {
-    Intcode::from(input)
+fn input_generator(input: &str) -> Result<Intcode, LoadError> {
+    input.parse()
 }
 
 // The remote control program executes the following steps in a loop forever:
@@ -66,38 +107,40 @@ fn input_generator(input: &str) -> Intcode {
 // 
 // Your puzzle answer was 308.
 #[aoc(day15, part1, Base)]
-fn solve_part1_base(vm: &Intcode) -> usize {
-    let mut all_vms = vec![(vm.clone(), (0, 0))];
+fn solve_part1_base(vm: &Intcode) -> Result<usize, Error> {
+    let mut all_vms = vec![Runner::new(vm.clone(), Droid::default())];
     let mut map: HashMap<(i64, i64), i8> = HashMap::new();
     let mut steps = None;
     'a: for i in 1.. {
-        for (prog, pos) in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
+        if all_vms.is_empty() {
+            break;
+        }
+        for prog in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
             for &dir in DIRECTIONS.iter() {
-                let new_pos = translate(&pos, dir);
+                let new_pos = translate(&prog.device.pos, dir);
                 if map.contains_key(&new_pos) {
                     continue;
                 }
-                let mut new = prog.clone();
-                new.inputs.push_back(dir.into());
-                match new.next().unwrap().unwrap() {
+                let (new, status) = probe(&prog, dir)?;
+                match status {
                     0 => {
                         map.insert(new_pos, WALL);
                     }
                     1 => {
                         map.insert(new_pos, SPACE);
-                        all_vms.push((new, new_pos));
+                        all_vms.push(new);
                     }
                     2 => {
                         map.insert(new_pos, OXYGEN);
                         steps = Some(i);
                         break 'a;
                     }
-                    _ => unreachable!(),
+                    status => return Err(format_err!("droid reported unknown status {}", status)),
                 }
             }
         }
     }
-    steps.unwrap()
+    steps.ok_or_else(|| err_msg("droid never found the oxygen system"))
 }
 
 // You quickly repair the oxygen system; oxygen gradually fills the area.
@@ -112,39 +155,38 @@ fn solve_part1_base(vm: &Intcode) -> usize {
 // 
 // Your puzzle answer was 328.
 #[aoc(day15, part2, Base)]
-fn solve_part2_base(vm: &Intcode) -> usize {
-    let mut all_vms = vec![(vm.clone(), (0, 0))];
+fn solve_part2_base(vm: &Intcode) -> Result<usize, Error> {
+    let mut all_vms = vec![Runner::new(vm.clone(), Droid::default())];
     let mut map: HashMap<(i64, i64), i8> = HashMap::new();
     let mut goal_prog = None;
-    'a: for _ in 1.. {
-        for (prog, pos) in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
+    'a: while !all_vms.is_empty() {
+        for prog in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
             for &dir in DIRECTIONS.iter() {
-                let new_pos = translate(&pos, dir);
+                let new_pos = translate(&prog.device.pos, dir);
                 if map.contains_key(&new_pos) {
                     continue;
                 }
-                let mut new = prog.clone();
-                new.inputs.push_back(dir.into());
-                match new.next().unwrap().unwrap() {
+                let (new, status) = probe(&prog, dir)?;
+                match status {
                     0 => {
                         map.insert(new_pos, WALL);
                     }
                     1 => {
                         map.insert(new_pos, SPACE);
-                        all_vms.push((new, new_pos));
+                        all_vms.push(new);
                     }
                     2 => {
                         map.insert(new_pos, OXYGEN);
-                        goal_prog = Some((new, new_pos));
+                        goal_prog = Some(new);
                         break 'a;
                     }
-                    _ => unreachable!(),
+                    status => return Err(format_err!("droid reported unknown status {}", status)),
                 }
             }
         }
     }
 
-    all_vms = vec![(goal_prog.unwrap())];
+    all_vms = vec![goal_prog.ok_or_else(|| err_msg("droid never found the oxygen system"))?];
 
     map = map.into_iter().filter(|(_k, v)| *v != SPACE.into()).collect();
 
@@ -154,28 +196,27 @@ fn solve_part2_base(vm: &Intcode) -> usize {
             minutes = Some(i - 1);
             break;
         }
-        for (prog, pos) in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
+        for prog in all_vms.drain(..).collect::<Vec<_>>().into_iter() {
             for &dir in DIRECTIONS.iter() {
-                let new_pos = translate(&pos, dir);
+                let new_pos = translate(&prog.device.pos, dir);
                 if map.contains_key(&new_pos) {
                     continue;
                 }
-                let mut new = prog.clone();
-                new.inputs.push_back(dir.into());
-                match new.next().unwrap().unwrap() {
+                let (new, status) = probe(&prog, dir)?;
+                match status {
                     0 => {
                         map.insert(new_pos, WALL);
                     }
                     1 => {
                         map.insert(new_pos, SPACE);
-                        all_vms.push((new, new_pos));
+                        all_vms.push(new);
                     }
-                    _ => unreachable!(),
+                    status => return Err(format_err!("droid reported unknown status {}", status)),
                 }
             }
         }
     }
-    minutes.unwrap()
+    minutes.ok_or_else(|| err_msg("oxygen never stopped spreading"))
 }
 
 fn translate(pos: &(i64, i64), direction: i8) -> (i64, i64) {
@@ -11,17 +11,23 @@
 //!  - "That computer ran Intcode programs like the gravity assist program it was working on; 
 //!     surely there are enough spare parts up there to build a new Intcode computer!"
 
-use std::str::FromStr;
-use crate::common::intcode_old::IntcodeVM;
+use crate::common::intcode::{vm::Intcode, error::{IntcodeError, LoadError}, memory::Memory, patch::Patches, symbolic::Symbolic};
+use failure::{err_msg, Error};
+
+#[aoc_generator(day2)]
+fn input_generator(input: &str) -> Result<Intcode, LoadError> {
+    input.parse()
+}
 
 // Magic smoke
-fn run_intcode(inputs: &str, a: i64, b: i64) -> i64 {
-    let mut vm = IntcodeVM::from_str(inputs).unwrap();
-    let state = vm.state_mut();
-    state.entry(1).and_modify(|e| *e = a);
-    state.entry(2).and_modify(|e| *e = b);
-    vm.execute().unwrap();
-    *vm.state().first().unwrap()
+/// Runs a copy of the program with `a` and `b` at addresses 1 and 2, returns what's left at 0.
+fn run_intcode(vm: &Intcode, a: i64, b: i64) -> Result<i64, IntcodeError> {
+    let mut vm = vm.clone();
+    let memory = vm.memory_mut();
+    memory.insert(1, a);
+    memory.insert(2, b);
+    vm.by_ref().collect::<Result<Vec<i64>, IntcodeError>>()?;
+    Ok(vm[0])
 }
 
 // Once you have a working computer,
@@ -32,8 +38,18 @@ fn run_intcode(inputs: &str, a: i64, b: i64) -> i64 {
 //  replace position 1 with the value 12 and replace position 2 with the value 2. 
 // What value is left at position 0 after the program halts?
 #[aoc(day2, part1, Loop)]
-fn solve_part1_loop(input: &str) -> i64 {
-    return run_intcode(input, 12, 2);
+fn solve_part1_loop(vm: &Intcode) -> Result<i64, Error> {
+    let mut vm = vm.clone();
+    alarm_1202(&mut vm)?;
+    vm.by_ref().collect::<Result<Vec<i64>, IntcodeError>>()?;
+    Ok(vm[0])
+}
+
+/// Restores the "1202 program alarm" state, the patch lives next to the puzzle input.
+fn alarm_1202(vm: &mut Intcode) -> Result<(), Error> {
+    let patches: Patches = include_str!("../input/2019/day2.patch").parse()?;
+    patches.get("alarm-1202")?.apply(vm)?;
+    Ok(())
 }
 
 // The inputs should still be provided to the program by replacing the values at addresses 1 and 2,
@@ -49,17 +65,27 @@ fn solve_part1_loop(input: &str) -> i64 {
 // 
 // Find the input noun and verb that cause the program to produce the output 19690720.
 #[aoc(day2, part2, Loop)]
-fn solve_part2_loop(input: &str) -> Result<usize, &str> {
+fn solve_part2_loop(vm: &Intcode) -> Result<usize, Error> {
     // Pretty crappy way of doing it but I can't think of a better solution at the moment...
     // Takes whopping 1.7ms, so slow :c 
     for a in 0..=99 {
         for b in 0..=99 { 
-            if run_intcode(input, a, b) == 19_690_720 {
+            if run_intcode(vm, a, b)? == 19_690_720 {
                 return Ok((100 * a + b) as usize)
             }
         }
     }
-    Err("failed to find result")
+    Err(err_msg("failed to find result"))
+}
+
+// Position 0 ends up linear in the noun and verb, so it can be solved for instead of searched.
+#[aoc(day2, part2, Symbolic)]
+fn solve_part2_symbolic(vm: &Intcode) -> Result<usize, Error> {
+    let mut symbolic = Symbolic::new(&vm.clone().memory());
+    let noun = symbolic.symbol_at(1, 0, 99);
+    let verb = symbolic.symbol_at(2, 0, 99);
+    let solution = symbolic.solve_memory(0, 19_690_720).ok_or_else(|| err_msg("failed to find result"))?;
+    Ok((100 * solution[noun] + solution[verb]) as usize)
 }
 
 
@@ -69,13 +95,15 @@ mod tests {
 
     // 1,0,0,0,99 becomes 2,0,0,0,99
     #[test]
-    fn day2_example1() {
-        assert_eq!(run_intcode("1,0,0,0,99", 0, 0), 2);
+    fn day2_example1() -> Result<(), IntcodeError> {
+        assert_eq!(run_intcode(&"1,0,0,0,99".parse()?, 0, 0)?, 2);
+        Ok(())
     }
 
     // 1,1,1,4,99,5,6,0,99 becomes 30,1,1,4,2,5,6,0,99.
     #[test]
-    fn day2_example2() {
-        assert_eq!(run_intcode("1,1,1,4,99,5,6,0,99", 1, 1), 30);
+    fn day2_example2() -> Result<(), IntcodeError> {
+        assert_eq!(run_intcode(&"1,1,1,4,99,5,6,0,99".parse()?, 1, 1)?, 30);
+        Ok(())
     }
 }
\ No newline at end of file
@@ -10,11 +10,11 @@
 
 use failure::Error;
 use std::str::FromStr;
-use crate::common::intcode_old::IntcodeVM;
+use crate::common::intcode::{channel::IntcodeVM, error::IntcodeError};
 
 #[aoc_generator(day5)]
-fn input_generator(input: &str) -> IntcodeVM {
-    IntcodeVM::from_str(input).unwrap()
+fn input_generator(input: &str) -> Result<IntcodeVM, IntcodeError> {
+    IntcodeVM::from_str(input)
 }
 
 // Finally, the program will output a diagnostic code and immediately halt.
@@ -5,13 +5,26 @@
 //!  you're going to need to send more power to your ship's thrusters to reach Santa in time. 
 //! To do this, you'll need to configure a series of amplifiers already installed on the ship.
 
-use std::str::FromStr;
-use crate::common::intcode_old::IntcodeVM;
+use crate::common::intcode::{
+    error::LoadError,
+    load,
+    scheduler::{Scheduler, Topology},
+    vm::Intcode,
+};
+use failure::{err_msg, Error};
 use permutohedron::Heap;
 
 #[aoc_generator(day7)]
-fn input_generator(input: &str) -> IntcodeVM {
-    IntcodeVM::from_str(input).unwrap()
+fn input_generator(input: &str) -> Result<Vec<i64>, LoadError> {
+    load::parse(input)
+}
+
+/// Runs one amplifier per phase setting, connected as `topology`, and returns the last signal out of the last one.
+fn amplify(code: &[i64], stages: &[i64], topology: Topology) -> Result<i64, Error> {
+    let mut amps: Vec<Intcode> = stages.iter().map(|&stage| Intcode::new(code.to_vec(), vec![stage])).collect();
+    amps[0].inputs.push_back(0);
+    let report = Scheduler::new(amps, topology).run()?;
+    report.outputs[stages.len() - 1].last().copied().ok_or_else(|| err_msg("last amplifier sent no signal"))
 }
 
 // There are five amplifiers connected in series;
@@ -39,18 +52,9 @@ fn input_generator(input: &str) -> IntcodeVM {
 //  the thrusters by trying every possible combination of phase settings on the amplifiers.
 // Make sure that memory is not shared or reused between copies of the program.
 #[aoc(day7, part1, Map)]
-fn solve_part1_map(vm: &IntcodeVM) -> Option<i64> {
-    Heap::new(&mut vec![0, 1, 2, 3, 4]).collect::<Vec<Vec<i64>>>().iter()
-        .map(|stages| {
-            let mut power = 0;
-            for stage in stages.iter() {
-                power = *vm.clone()
-                    .simple_input(vec![*stage, power])
-                    .execute_and_collect().unwrap().last().unwrap();
-            }
-            power
-        })
-        .max()
+fn solve_part1_map(code: &[i64]) -> Result<i64, Error> {
+    Heap::new(&mut vec![0, 1, 2, 3, 4])
+        .try_fold(i64::MIN, |best, stages| Ok(best.max(amplify(code, &stages, Topology::Pipeline)?)))
 }
 
 // It's no good - in this configuration,
@@ -81,33 +85,7 @@ fn solve_part1_map(vm: &IntcodeVM) -> Option<i64> {
 // Your job is to find the largest output signal that can be sent to
 //  the thrusters using the new phase settings and feedback loop arrangement.
 #[aoc(day7, part2, Map)]
-fn solve_part2_map(vm: &IntcodeVM) -> Option<i64> {
-    Heap::new(&mut vec![5, 6, 7, 8, 9]).collect::<Vec<Vec<i64>>>().iter()
-        .map(|stages| {
-            let mut rx = std::collections::VecDeque::new();
-            let mut tx = std::collections::VecDeque::new();
-            for _ in 0..=5 {
-                let (t, r) = std::sync::mpsc::channel::<Option<i64>>();
-                rx.push_back(r);
-                tx.push_back(t);
-            }
-            for (stage, t) in stages.iter().zip(tx.iter()) {
-                t.send(Some(*stage)).unwrap();
-            }
-            let txa = tx.pop_front().unwrap();
-            let rxe = rx.pop_back().unwrap();
-            txa.send(Some(0)).unwrap();
-            for (r, t) in rx.into_iter().zip(tx.into_iter()) {
-                let mut amp = vm.clone();
-                amp.rx(r).tx(t);
-                std::thread::spawn(move || amp.execute());
-            }
-            let mut last = -1;
-            for z in rxe {
-                last = z.unwrap();
-                let _ = txa.send(z);
-            }
-            last
-        })
-        .max()
+fn solve_part2_map(code: &[i64]) -> Result<i64, Error> {
+    Heap::new(&mut vec![5, 6, 7, 8, 9])
+        .try_fold(i64::MIN, |best, stages| Ok(best.max(amplify(code, &stages, Topology::Ring)?)))
 }
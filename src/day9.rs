@@ -12,12 +12,15 @@
 //!  passes some checks to demonstrate it is a complete Intcode computer.
 
 use failure::Error;
-use std::str::FromStr;
-use crate::common::intcode_old::IntcodeVM;
+use std::collections::BTreeMap;
+use crate::common::intcode::channel::IntcodeVM;
+use crate::common::intcode::{vm::{Intcode, OverflowPolicy}, error::{IntcodeError, LoadError}, load, memory::{Memory, Dense, Paged}, threaded::Threaded};
+#[cfg(feature = "bigint")]
+use crate::common::intcode::big::BigIntcode;
 
 #[aoc_generator(day9)]
-fn input_generator(input: &str) -> IntcodeVM {
-    IntcodeVM::from_str(input).unwrap()
+fn input_generator(input: &str) -> Result<Vec<i64>, LoadError> {
+    load::parse(input)
 }
 
 // Your existing Intcode computer is missing one key feature: 
@@ -58,8 +61,8 @@ fn input_generator(input: &str) -> IntcodeVM {
 // 
 // Your puzzle answer was 2789104029.
 #[aoc(day9, part1, IntcodeVM)]
-fn solve_part1_intcode(vm: &IntcodeVM) -> Result<i64, Error> {
-    let output = vm.clone().simple_input(vec![1]).execute_and_collect()?;
+fn solve_part1_intcode(code: &[i64]) -> Result<i64, Error> {
+    let output = IntcodeVM::new(code.to_vec()).simple_input(vec![1]).execute_and_collect()?;
     Ok(*output.first().expect("expected output to contain at least one value"))
 }
 
@@ -79,7 +82,77 @@ fn solve_part1_intcode(vm: &IntcodeVM) -> Result<i64, Error> {
 // 
 // Your puzzle answer was 32869.
 #[aoc(day9, part2, IntcodeVM)]
-fn solve_part2_intcode(vm: &IntcodeVM) -> Result<i64, Error> {
-    let output = vm.clone().simple_input(vec![2]).execute_and_collect()?;
+fn solve_part2_intcode(code: &[i64]) -> Result<i64, Error> {
+    let output = IntcodeVM::new(code.to_vec()).simple_input(vec![2]).execute_and_collect()?;
     Ok(*output.first().expect("expected output to contain at least one value"))
-}
\ No newline at end of file
+}
+
+/// Runs BOOST in test mode, which outputs only the keycode when every check passes.
+fn self_test(code: &[i64], policy: OverflowPolicy) -> Result<i64, Error> {
+    let mut vm = Intcode::new(code.to_vec(), vec![1]);
+    vm.overflow(policy);
+    match vm.collect::<Result<Vec<i64>, IntcodeError>>()?.as_slice() {
+        [keycode] => Ok(*keycode),
+        failed => Err(format_err!("BOOST self-test failed for {:?}", failed)),
+    }
+}
+
+#[aoc(day9, part1, Panic)]
+fn solve_part1_panic(code: &[i64]) -> Result<i64, Error> {
+    self_test(code, OverflowPolicy::Panic)
+}
+
+#[aoc(day9, part1, Wrap)]
+fn solve_part1_wrap(code: &[i64]) -> Result<i64, Error> {
+    self_test(code, OverflowPolicy::Wrap)
+}
+
+#[aoc(day9, part1, Saturate)]
+fn solve_part1_saturate(code: &[i64]) -> Result<i64, Error> {
+    self_test(code, OverflowPolicy::Saturate)
+}
+
+#[aoc(day9, part1, Checked)]
+fn solve_part1_checked(code: &[i64]) -> Result<i64, Error> {
+    self_test(code, OverflowPolicy::Error)
+}
+
+#[cfg(feature = "bigint")]
+#[aoc(day9, part1, BigInt)]
+fn solve_part1_bigint(code: &[i64]) -> Result<String, Error> {
+    let output = BigIntcode::new(code.iter().map(|&x| x.into()).collect(), vec![1.into()])
+        .collect::<Result<Vec<_>, IntcodeError>>()?;
+    match output.as_slice() {
+        [keycode] => Ok(keycode.to_string()),
+        failed => Err(format_err!("BOOST self-test failed for {:?}", failed)),
+    }
+}
+
+// Same thing on the stepping VM, once per memory backend, so `cargo aoc bench` can compare them.
+fn boost<M: Memory>(code: &[i64], mode: i64) -> Result<i64, Error> {
+    let output = Intcode::<M>::with_backend(code.to_vec(), vec![mode])
+        .collect::<Result<Vec<i64>, IntcodeError>>()?;
+    Ok(*output.first().expect("expected output to contain at least one value"))
+}
+
+#[aoc(day9, part2, BTreeMap)]
+fn solve_part2_btreemap(code: &[i64]) -> Result<i64, Error> {
+    boost::<BTreeMap<usize, i64>>(code, 2)
+}
+
+#[aoc(day9, part2, Dense)]
+fn solve_part2_dense(code: &[i64]) -> Result<i64, Error> {
+    boost::<Dense>(code, 2)
+}
+
+#[aoc(day9, part2, Paged)]
+fn solve_part2_paged(code: &[i64]) -> Result<i64, Error> {
+    boost::<Paged>(code, 2)
+}
+
+#[aoc(day9, part2, Threaded)]
+fn solve_part2_threaded(code: &[i64]) -> Result<i64, Error> {
+    let output = Threaded::from(Intcode::new(code.to_vec(), vec![2]))
+        .collect::<Result<Vec<i64>, IntcodeError>>()?;
+    Ok(*output.first().expect("expected output to contain at least one value"))
+}
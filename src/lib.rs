@@ -8,6 +8,8 @@ extern crate pest;
 #[macro_use] extern crate aoc_runner_derive;
 extern crate gen_iter;
 extern crate num_integer;
+#[cfg(feature = "bigint")] extern crate num_bigint;
+#[cfg(feature = "bigint")] extern crate num_traits;
 
 pub mod common;
 